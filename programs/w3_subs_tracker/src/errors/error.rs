@@ -4,6 +4,58 @@ use anchor_lang::prelude::*;
 pub enum MainStateError {
     #[msg("MainState is already initialized")]
     MainStateAlreadyInitialized,
+    #[msg("There is no pending proposal")]
+    NoPendingProposal,
+    #[msg("Multisig needs 1 to 5 distinct signers and threshold between 1 and the number of signers")]
+    InvalidMultisig,
+    #[msg("Not enough multisig signers signed the transaction")]
+    MultisigThresholdNotMet,
+    #[msg("Instruction is paused")]
+    InstructionPaused,
+    #[msg("Emergency withdrawal is available only while unsubscribe is paused")]
+    UnsubscribeNotPaused,
+    #[msg("Pause mask contains unknown flags")]
+    InvalidPauseMask,
+    #[msg("Fees in basis points can't be over 10000")]
+    InvalidFeeSchedule,
+    #[msg("Config delay can't be below MIN_CONFIG_DELAY (1 day)")]
+    InvalidConfigDelay,
+    #[msg("Pending config is not effective yet")]
+    ConfigNotEffective,
+    #[msg("Main state is being sunset")]
+    SunsetStarted,
+    #[msg("Main state is not being sunset")]
+    SunsetNotStarted,
+    #[msg("Main state still has open subscriptions")]
+    OpenSubscriptions,
+    #[msg("Treasury still holds funds not swept to the payout address")]
+    TreasuryNotSwept,
+    #[msg("Treasury still owes referral rewards not claimed by the referrers")]
+    ReferralRewardsUnclaimed,
+    #[msg("Grace period can't be negative")]
+    InvalidGracePeriod,
+}
+
+#[error_code]
+pub enum PlanError {
+    #[msg("Plan name is too long")]
+    NameTooLong,
+    #[msg("Plan period must be greater than 0")]
+    InvalidPeriod,
+    #[msg("Plan is retired")]
+    PlanRetired,
+    #[msg("Plan doesn't belong to the given main state")]
+    IncorrectMainState,
+    #[msg("Plan is not the desired plan of the subscription")]
+    NotDesiredPlan,
+    #[msg("Plan is not the current plan of the subscription")]
+    NotCurrentPlan,
+    #[msg("Plan doesn't offer a free trial")]
+    NoTrial,
+    #[msg("Plan is already the current plan of the subscription")]
+    SamePlan,
+    #[msg("Plan has no metered usage")]
+    NotMetered,
 }
 
 #[error_code]
@@ -26,4 +78,51 @@ pub enum SubscriptionError {
     AirthemticError,
     #[msg("Obligatory account is not found in the context")]
     MissingObligatoryAccount,
-}
\ No newline at end of file
+    #[msg("Subscription period is still active")]
+    ActiveSubscription,
+    #[msg("Auto renewal is not enabled for the subscription")]
+    AutoRenewDisabled,
+    #[msg("Subscription has no active period")]
+    NoActiveSubscription,
+    #[msg("Tier of the current plan is lower than required")]
+    InsufficientTier,
+    #[msg("Mint is not accepted by the main state")]
+    InvalidMint,
+    #[msg("Token account doesn't belong to the receiver")]
+    InvalidTokenAccount,
+    #[msg("Subscription still holds credits or locked debits")]
+    SubscriptionNotEmpty,
+    #[msg("Free trial was already used by the wallet")]
+    TrialAlreadyUsed,
+    #[msg("User can't refer own subscription")]
+    InvalidReferrer,
+    #[msg("Subscription can't move from its current status to the requested one")]
+    InvalidStatusTransition,
+    #[msg("Subscription is suspended")]
+    SubscriptionSuspended,
+    #[msg("Usage would exceed the usage cap of the plan for the period")]
+    UsageCapExceeded,
+}
+#[error_code]
+pub enum MigrationError {
+    #[msg("Account is already migrated to the current layout")]
+    AlreadyMigrated,
+    #[msg("Account is not a legacy account of this program")]
+    InvalidLegacyAccount,
+}
+
+#[error_code]
+pub enum CouponError {
+    #[msg("Main state has no coupon signer")]
+    CouponSignerNotSet,
+    #[msg("Coupon signature instruction is missing")]
+    MissingSignature,
+    #[msg("Coupon is not signed by the coupon signer")]
+    InvalidSignature,
+    #[msg("Coupon is issued for other main state or plan")]
+    InvalidCoupon,
+    #[msg("Coupon is expired")]
+    CouponExpired,
+    #[msg("Coupon reached its max uses")]
+    CouponExhausted,
+}
@@ -0,0 +1,343 @@
+use anchor_lang::prelude::*;
+use crate::state::main_state::{FeeSchedule, PendingConfig, Roles};
+use crate::state::subscription::SubscriptionStatus;
+
+#[event]
+pub struct MainStateInitialized {
+    pub main_state: Pubkey,
+    pub tenant_id: u64,
+    pub owner: Pubkey,
+    pub fee_schedule: FeeSchedule,
+    pub accepted_mint: Option<Pubkey>,
+}
+
+// emitted with None when the proposal is cancelled
+#[event]
+pub struct AuthorityProposed {
+    pub main_state: Pubkey,
+    pub pending_authority: Option<Pubkey>,
+}
+
+#[event]
+pub struct AuthorityUpdated {
+    pub main_state: Pubkey,
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+// emitted with None when the proposal is cancelled
+#[event]
+pub struct OwnerProposed {
+    pub main_state: Pubkey,
+    pub pending_owner: Option<Pubkey>,
+}
+
+#[event]
+pub struct OwnerUpdated {
+    pub main_state: Pubkey,
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
+
+#[event]
+pub struct MultisigSet {
+    pub main_state: Pubkey,
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct ConfigQueued {
+    pub main_state: Pubkey,
+    // None when the pending config was cancelled
+    pub pending_config: Option<PendingConfig>,
+}
+
+#[event]
+pub struct FeesUpdated {
+    pub main_state: Pubkey,
+    pub old_fee_schedule: FeeSchedule,
+    pub new_fee_schedule: FeeSchedule,
+}
+
+#[event]
+pub struct ConfigApplied {
+    pub main_state: Pubkey,
+    pub fee_schedule: FeeSchedule,
+    pub roles: Roles,
+    pub config_delay: i64,
+    pub renew_bounty_lamports: u64,
+}
+
+#[event]
+pub struct PlanCreated {
+    pub main_state: Pubkey,
+    pub plan: Pubkey,
+    pub plan_id: u64,
+    pub price_lamports: u64,
+    pub period: i64,
+    pub tier: u8,
+}
+
+#[event]
+pub struct PlanUpdated {
+    pub main_state: Pubkey,
+    pub plan: Pubkey,
+    pub price_lamports: u64,
+    pub period: i64,
+    pub tier: u8,
+}
+
+#[event]
+pub struct PlanRetired {
+    pub main_state: Pubkey,
+    pub plan: Pubkey,
+}
+
+#[event]
+pub struct PlanTrialSet {
+    pub main_state: Pubkey,
+    pub plan: Pubkey,
+    pub trial_period: i64,
+}
+
+#[event]
+pub struct SubscriptionCreated {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub user: Pubkey,
+    pub desired_plan: Pubkey,
+    pub initial_deposit: u64,
+}
+
+#[event]
+pub struct Funded {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub amount: u64,
+    // credits after the deposit
+    pub credits: u64,
+}
+
+#[event]
+pub struct DesiredTypeChanged {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub desired_plan: Pubkey,
+}
+
+#[event]
+pub struct SubscriptionActivated {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub plan: Pubkey,
+    pub valid_till: i64,
+    pub price_lamports: u64,
+}
+
+#[event]
+pub struct AutoRenewSet {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub auto_renew: bool,
+}
+
+#[event]
+pub struct Renewed {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub plan: Pubkey,
+    pub keeper: Pubkey,
+    pub valid_till: i64,
+    pub bounty_lamports: u64,
+}
+
+#[event]
+pub struct InfoSet {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub current_plan: Pubkey,
+    pub valid_till: i64,
+    pub locked: u64,
+}
+
+#[event]
+pub struct Unsubscribed {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub withdraw_content: bool,
+    // funds paid back to the user, 0 without withdrawal
+    pub refunded: u64,
+    // fees charged from the unused debits and the withdrawn credits
+    pub fee: u64,
+    // credits left after unsubscribing
+    pub credits: u64,
+}
+
+#[event]
+pub struct Withdrawn {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64,
+    // part of the amount accrued to the referrer of the subscription
+    pub referral_share: u64,
+}
+
+#[event]
+pub struct EmergencyCreditsWithdrawn {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CouponRedeemed {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub plan: Pubkey,
+    pub nonce: u64,
+    // price paid for the period after the discount
+    pub price: u64,
+    pub uses: u32,
+}
+
+#[event]
+pub struct TrialStarted {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub user: Pubkey,
+    pub plan: Pubkey,
+    pub valid_till: i64,
+}
+
+#[event]
+pub struct SubscriptionClosed {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub user: Pubkey,
+    // earned funds and owed fees moved to the treasury before closing
+    pub paid_to_treasury: u64,
+}
+
+#[event]
+pub struct PauseSet {
+    pub main_state: Pubkey,
+    pub paused: u16,
+}
+
+#[event]
+pub struct PlanChanged {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub from_plan: Pubkey,
+    pub to_plan: Pubkey,
+    // unused part of the previous period moved to credits, after the fee
+    pub credited: u64,
+    pub fee: u64,
+    pub price_lamports: u64,
+    pub valid_till: i64,
+}
+
+#[event]
+pub struct PlanMeteringSet {
+    pub main_state: Pubkey,
+    pub plan: Pubkey,
+    pub unit_price: u64,
+    pub usage_cap: Option<u64>,
+}
+
+#[event]
+pub struct UsageRecorded {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub plan: Pubkey,
+    pub units: u64,
+    pub amount: u64,
+    // units recorded in the period so far
+    pub period_units: u64,
+    pub credits: u64,
+}
+
+#[event]
+pub struct StatusChanged {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub from: SubscriptionStatus,
+    pub to: SubscriptionStatus,
+}
+
+#[event]
+pub struct GracePeriodSet {
+    pub main_state: Pubkey,
+    pub grace_period: i64,
+}
+
+#[event]
+pub struct ReferralBpsSet {
+    pub main_state: Pubkey,
+    pub referral_bps: u16,
+}
+
+#[event]
+pub struct ReferralRewardsClaimed {
+    pub main_state: Pubkey,
+    pub referrer: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CouponSignerSet {
+    pub main_state: Pubkey,
+    pub coupon_signer: Option<Pubkey>,
+}
+
+#[event]
+pub struct PayoutAddressSet {
+    pub main_state: Pubkey,
+    pub payout_address: Pubkey,
+}
+
+#[event]
+pub struct TreasurySwept {
+    pub main_state: Pubkey,
+    pub payout: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct MainStateMigrated {
+    pub main_state: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+}
+
+#[event]
+pub struct SubscriptionMigrated {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+}
+
+#[event]
+pub struct SunsetStarted {
+    pub main_state: Pubkey,
+    pub sunset_at: i64,
+}
+
+#[event]
+pub struct SunsetRefunded {
+    pub subscription: Pubkey,
+    pub main_state: Pubkey,
+    pub user: Pubkey,
+    // credits and the unused part of the running period paid back to the user
+    pub refund: u64,
+    pub paid_to_treasury: u64,
+}
+
+#[event]
+pub struct MainStateClosed {
+    pub main_state: Pubkey,
+    pub owner: Pubkey,
+}
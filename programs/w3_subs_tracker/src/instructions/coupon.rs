@@ -0,0 +1,90 @@
+use anchor_lang::prelude::*;
+use solana_program::sysvar::instructions::ID as INSTRUCTIONS_SYSVAR_ID;
+use crate::state::coupon::*;
+use crate::state::main_state::MainState;
+use crate::state::plan::Plan;
+use crate::state::subscription::Subscription;
+
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(coupon: Coupon)]
+pub struct ActivateWithCoupon<'info> {
+    #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    pub main_state: Account<'info, MainState>,
+    pub plan: Account<'info, Plan>,
+    #[account(init_if_needed, payer = user, space = 8 + CouponRedemption::INIT_SPACE, seeds = [b"coupon", main_state.key().as_ref(), coupon.nonce.to_le_bytes().as_ref()], bump)]
+    pub coupon_redemption: Account<'info, CouponRedemption>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: Instructions sysvar, checked by the address
+    #[account(address = INSTRUCTIONS_SYSVAR_ID)]
+    pub instructions: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+pub mod processor {
+    use crate::errors::error::{CouponError, PlanError, SubscriptionError};
+    use crate::events::event::*;
+    use crate::instructions::subscription::processor::{check_plan, start_period};
+    use crate::state::main_state::PAUSE_ACTIVATE;
+    use crate::utils::math;
+    use crate::utils::signature::verify_ed25519;
+
+    use super::*;
+
+    /**
+     * Same as activate_subscription, with the plan price discounted by the coupon signed by the coupon signer of the main state.
+     * The signature is verified by the ed25519 program instruction placed right before this one.
+     */
+    pub fn activate_with_coupon(ctx: Context<ActivateWithCoupon>, coupon: Coupon) -> Result<()> {
+        msg!("Params: {:?}", coupon);
+        let main_state = &ctx.accounts.main_state;
+        main_state.check_not_paused(PAUSE_ACTIVATE)?;
+        main_state.check_not_sunset()?;
+        let coupon_signer = main_state.coupon_signer.ok_or(CouponError::CouponSignerNotSet)?;
+        verify_ed25519(&ctx.accounts.instructions, &coupon_signer, &coupon.try_to_vec()?)?;
+        if coupon.main_state != main_state.key() || coupon.plan != ctx.accounts.plan.key() {
+            return Err(CouponError::InvalidCoupon.into());
+        }
+        let now = ctx.accounts.clock.unix_timestamp * 1000;
+        if coupon.expires_at <= now {
+            return Err(CouponError::CouponExpired.into());
+        }
+        let redemption = &mut ctx.accounts.coupon_redemption;
+        if redemption.uses >= coupon.max_uses {
+            return Err(CouponError::CouponExhausted.into());
+        }
+        redemption.main_state = main_state.key();
+        redemption.nonce = coupon.nonce;
+        redemption.uses += 1;
+        redemption.bump = ctx.bumps.coupon_redemption;
+
+        let subscription = &mut ctx.accounts.subscription;
+        if subscription.imutable_initialized.main_state_pda != main_state.key() {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if subscription.authority_writable.valid_till > now {
+            return Err(SubscriptionError::ActiveSubscription.into());
+        }
+        if subscription.subscription_status_writable.desired_plan != ctx.accounts.plan.key() {
+            return Err(PlanError::NotDesiredPlan.into());
+        }
+        check_plan(&ctx.accounts.plan, &main_state.key())?;
+        let plan_price = ctx.accounts.plan.price_lamports;
+        let percent_off = math::fee_bps(plan_price, coupon.percent_off_bps, 0)?;
+        let price = plan_price.saturating_sub(percent_off).saturating_sub(coupon.amount_off);
+        start_period(subscription, &ctx.accounts.plan, price, main_state, now)?;
+        emit_cpi!(CouponRedeemed {
+            subscription: subscription.key(),
+            main_state: main_state.key(),
+            plan: ctx.accounts.plan.key(),
+            nonce: coupon.nonce,
+            price,
+            uses: redemption.uses,
+        });
+        Ok(())
+    }
+}
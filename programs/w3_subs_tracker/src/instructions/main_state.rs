@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
-use crate::state::main_state::MainState;
+use anchor_spl::token::Token;
+use crate::state::main_state::{FeeSchedule, MainState, Multisig, Role, Roles, MAIN_STATE_VERSION, MAX_MULTISIG_SIGNERS, MIN_CONFIG_DELAY, PAUSE_ALL};
+use crate::state::treasury::Treasury;
 
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct UpdateOwner<'info> {
     #[account(mut)]
@@ -10,15 +13,29 @@ pub struct UpdateOwner<'info> {
     pub signer: Signer<'info>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
+#[instruction(tenant_id: u64)]
 pub struct InitializeMainState<'info> {
-    #[account(init, payer = user, space = 8 + 64 + 1, seeds=["mainState".as_bytes()], bump)]
+    #[account(init, payer = user, space = 8 + MainState::INIT_SPACE, seeds=["mainState".as_bytes(), user.key().as_ref(), tenant_id.to_le_bytes().as_ref()], bump)]
     pub main_state: Account<'info, MainState>,
     #[account(mut)]
     pub user: Signer<'info>,
+    // mint of the SPL token the subscriptions are paid in, subscriptions are paid in native SOL when not provided
+    /// CHECK: Unpacked as SPL token mint in the instruction
+    pub accepted_mint: Option<UncheckedAccount<'info>>,
+    #[account(init, payer = user, space = 8 + Treasury::INIT_SPACE, seeds = [b"treasury", main_state.key().as_ref()], bump)]
+    pub treasury: Account<'info, Treasury>,
+    // token account of the treasury, obligatory only with accepted mint
+    /// CHECK: Treasury vault token account owned by the treasury PDA, created in the instruction
+    #[account(mut, seeds = [b"treasury_vault", main_state.key().as_ref()], bump)]
+    pub treasury_vault: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
     pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct UpdateAuthority<'info> {
     #[account(mut)]
@@ -28,50 +45,482 @@ pub struct UpdateAuthority<'info> {
 
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct UpdateFees<'info> {
     #[account(mut)]
     pub main_state: Account<'info, MainState>,
     #[account(signer)]
     pub signer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateRenewBounty<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetMultisig<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateRole<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetPause<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetPayoutAddress<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetCouponSigner<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetGracePeriod<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ApplyPendingConfig<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    pub clock: Sysvar<'info, Clock>,
 }
 
 pub mod processor {
     use crate::errors::error::MainStateError;
+    use crate::events::event::*;
+    use crate::errors::error::SubscriptionError;
+    use crate::utils::funds::{create_token_account, unpack_mint};
 
     use super::*;
-    pub fn intialize_main_state(ctx: Context<InitializeMainState>, fees: u8) -> Result<()> {
+    pub fn intialize_main_state(ctx: Context<InitializeMainState>, tenant_id: u64, fee_schedule: FeeSchedule, config_delay: i64) -> Result<()> {
+        fee_schedule.validate()?;
+        if config_delay < MIN_CONFIG_DELAY {
+            return Err(MainStateError::InvalidConfigDelay.into());
+        }
         let main_state = &mut ctx.accounts.main_state;
+        main_state.version = MAIN_STATE_VERSION;
         main_state.owner = *ctx.accounts.user.key;
         main_state.authority = *ctx.accounts.user.key;
-        main_state.unsubscribe_fee = fees;
+        main_state.fee_schedule = fee_schedule;
+        main_state.tenant_id = tenant_id;
+        main_state.bump = ctx.bumps.main_state;
+        main_state.roles = Roles {
+            verifier: Some(*ctx.accounts.user.key),
+            treasurer: Some(*ctx.accounts.user.key),
+            fee_manager: Some(*ctx.accounts.user.key),
+            pauser: Some(*ctx.accounts.user.key),
+        };
+        main_state.payout_address = *ctx.accounts.user.key;
+        main_state.config_delay = config_delay;
+        main_state.accepted_mint = match &ctx.accounts.accepted_mint {
+            Some(mint) => Some(unpack_mint(mint)?),
+            None => None,
+        };
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.main_state = main_state.key();
+        treasury.bump = ctx.bumps.treasury;
+        if let Some(mint) = &ctx.accounts.accepted_mint {
+            match (&ctx.accounts.treasury_vault, &ctx.accounts.token_program) {
+                (Some(treasury_vault), Some(token_program)) => {
+                    let main_state_key = main_state.key();
+                    let vault_seeds: &[&[u8]] = &[b"treasury_vault", main_state_key.as_ref(), &[ctx.bumps.treasury_vault.unwrap()]];
+                    create_token_account(&ctx.accounts.user, treasury_vault, vault_seeds, &mint.to_account_info(), &treasury.to_account_info(), &ctx.accounts.system_program, token_program)?;
+                },
+                _ => return Err(SubscriptionError::MissingObligatoryAccount.into()),
+            }
+        }
+        emit_cpi!(MainStateInitialized {
+            main_state: main_state.key(),
+            tenant_id,
+            owner: main_state.owner,
+            fee_schedule,
+            accepted_mint: main_state.accepted_mint,
+        });
         Ok(())
     }
 
-    pub fn update_authority(ctx: Context<UpdateAuthority>, new_authority: Pubkey) -> Result<()> {
+    /**
+     * Proposes the new authority, the current one stays in charge until the proposed one accepts it.
+     */
+    pub fn propose_authority(ctx: Context<UpdateAuthority>, new_authority: Pubkey) -> Result<()> {
+        msg!("Params: {:?}", new_authority);
         let main_state = &mut ctx.accounts.main_state;
         if main_state.owner != *ctx.accounts.signer.key {
             return Err(ErrorCode::ConstraintAddress.into());
         }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        main_state.pending_authority = Some(new_authority);
+        emit_cpi!(AuthorityProposed {
+            main_state: main_state.key(),
+            pending_authority: main_state.pending_authority,
+        });
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<UpdateAuthority>) -> Result<()> {
+        let main_state = &mut ctx.accounts.main_state;
+        let new_authority = main_state.pending_authority.ok_or(MainStateError::NoPendingProposal)?;
+        if new_authority != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        let old_authority = main_state.authority;
         main_state.authority = new_authority;
+        main_state.pending_authority = None;
+        emit_cpi!(AuthorityUpdated {
+            main_state: main_state.key(),
+            old_authority,
+            new_authority,
+        });
         Ok(())
     }
 
-    pub fn update_owner(ctx: Context<UpdateOwner>, new_owner: Pubkey) -> Result<()> {
+    pub fn cancel_authority_proposal(ctx: Context<UpdateAuthority>) -> Result<()> {
         let main_state = &mut ctx.accounts.main_state;
         if main_state.owner != *ctx.accounts.signer.key {
             return Err(ErrorCode::ConstraintAddress.into());
         }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        if main_state.pending_authority.is_none() {
+            return Err(MainStateError::NoPendingProposal.into());
+        }
+        main_state.pending_authority = None;
+        emit_cpi!(AuthorityProposed {
+            main_state: main_state.key(),
+            pending_authority: None,
+        });
+        Ok(())
+    }
+
+    /**
+     * Proposes the new owner, the current one stays in charge until the proposed one accepts it.
+     */
+    pub fn propose_owner(ctx: Context<UpdateOwner>, new_owner: Pubkey) -> Result<()> {
+        msg!("Params: {:?}", new_owner);
+        let main_state = &mut ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        main_state.pending_owner = Some(new_owner);
+        emit_cpi!(OwnerProposed {
+            main_state: main_state.key(),
+            pending_owner: main_state.pending_owner,
+        });
+        Ok(())
+    }
+
+    pub fn accept_owner(ctx: Context<UpdateOwner>) -> Result<()> {
+        let main_state = &mut ctx.accounts.main_state;
+        let new_owner = main_state.pending_owner.ok_or(MainStateError::NoPendingProposal)?;
+        if new_owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        let old_owner = main_state.owner;
         main_state.owner = new_owner;
+        main_state.pending_owner = None;
+        emit_cpi!(OwnerUpdated {
+            main_state: main_state.key(),
+            old_owner,
+            new_owner,
+        });
         Ok(())
     }
 
-    pub fn update_fees(ctx: Context<UpdateFees>, new_fees: u8) -> Result<()> {
+    pub fn cancel_owner_proposal(ctx: Context<UpdateOwner>) -> Result<()> {
         let main_state = &mut ctx.accounts.main_state;
         if main_state.owner != *ctx.accounts.signer.key {
             return Err(ErrorCode::ConstraintAddress.into());
         }
-        main_state.unsubscribe_fee = new_fees;
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        if main_state.pending_owner.is_none() {
+            return Err(MainStateError::NoPendingProposal.into());
+        }
+        main_state.pending_owner = None;
+        emit_cpi!(OwnerProposed {
+            main_state: main_state.key(),
+            pending_owner: None,
+        });
         Ok(())
     }
-}
\ No newline at end of file
+
+    pub fn update_fees(ctx: Context<UpdateFees>, new_fee_schedule: FeeSchedule) -> Result<()> {
+        msg!("Params: {:?}", new_fee_schedule);
+        let main_state = &mut ctx.accounts.main_state;
+        if !main_state.has_role(Role::FeeManager, ctx.accounts.signer.key) {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        new_fee_schedule.validate()?;
+        let pending_config = main_state.queue_config(ctx.accounts.clock.unix_timestamp * 1000)?;
+        pending_config.fee_schedule = Some(new_fee_schedule);
+        emit_cpi!(ConfigQueued {
+            main_state: main_state.key(),
+            pending_config: main_state.pending_config.clone(),
+        });
+        Ok(())
+    }
+
+    pub fn update_renew_bounty(ctx: Context<UpdateRenewBounty>, new_bounty_lamports: u64) -> Result<()> {
+        msg!("Params: {:?}", new_bounty_lamports);
+        let main_state = &mut ctx.accounts.main_state;
+        if !main_state.has_role(Role::FeeManager, ctx.accounts.signer.key) {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        let pending_config = main_state.queue_config(ctx.accounts.clock.unix_timestamp * 1000)?;
+        pending_config.renew_bounty_lamports = Some(new_bounty_lamports);
+        emit_cpi!(ConfigQueued {
+            main_state: main_state.key(),
+            pending_config: main_state.pending_config.clone(),
+        });
+        Ok(())
+    }
+
+    /**
+     * Sets the M-of-N signer set co-signing the sensitive admin instructions, no signers with zero threshold removes it.
+     * Changing an existing set needs the approval of the current one.
+     */
+    pub fn set_multisig(ctx: Context<SetMultisig>, signers: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        msg!("Params: {:?}, {:?}", signers, threshold);
+        let main_state = &mut ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        if signers.is_empty() && threshold == 0 {
+            main_state.multisig = None;
+        } else {
+            let distinct = signers.iter().enumerate().all(|(i, signer)| !signers[..i].contains(signer));
+            if signers.len() > MAX_MULTISIG_SIGNERS || !distinct || threshold == 0 || threshold as usize > signers.len() {
+                return Err(MainStateError::InvalidMultisig.into());
+            }
+            main_state.multisig = Some(Multisig { signers: signers.clone(), threshold });
+        }
+        emit_cpi!(MultisigSet {
+            main_state: main_state.key(),
+            signers,
+            threshold,
+        });
+        Ok(())
+    }
+
+    pub fn grant_role(ctx: Context<UpdateRole>, role: Role, grantee: Pubkey) -> Result<()> {
+        msg!("Params: {:?}, {:?}", role, grantee);
+        set_role(ctx, role, Some(grantee))
+    }
+
+    pub fn revoke_role(ctx: Context<UpdateRole>, role: Role) -> Result<()> {
+        msg!("Params: {:?}", role);
+        set_role(ctx, role, None)
+    }
+
+    fn set_role(ctx: Context<UpdateRole>, role: Role, holder: Option<Pubkey>) -> Result<()> {
+        let main_state = &mut ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        let current_roles = main_state.roles;
+        let pending_config = main_state.queue_config(ctx.accounts.clock.unix_timestamp * 1000)?;
+        // role changes queued before are kept
+        let roles = pending_config.roles.get_or_insert(current_roles);
+        match role {
+            Role::Verifier => roles.verifier = holder,
+            Role::Treasurer => roles.treasurer = holder,
+            Role::FeeManager => roles.fee_manager = holder,
+            Role::Pauser => roles.pauser = holder,
+        }
+        emit_cpi!(ConfigQueued {
+            main_state: main_state.key(),
+            pending_config: main_state.pending_config.clone(),
+        });
+        Ok(())
+    }
+
+    pub fn set_config_delay(ctx: Context<UpdateConfig>, config_delay: i64) -> Result<()> {
+        msg!("Params: {:?}", config_delay);
+        let main_state = &mut ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        if config_delay < MIN_CONFIG_DELAY {
+            return Err(MainStateError::InvalidConfigDelay.into());
+        }
+        let pending_config = main_state.queue_config(ctx.accounts.clock.unix_timestamp * 1000)?;
+        pending_config.config_delay = Some(config_delay);
+        emit_cpi!(ConfigQueued {
+            main_state: main_state.key(),
+            pending_config: main_state.pending_config.clone(),
+        });
+        Ok(())
+    }
+
+    pub fn cancel_pending_config(ctx: Context<UpdateConfig>) -> Result<()> {
+        let main_state = &mut ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        if main_state.pending_config.is_none() {
+            return Err(MainStateError::NoPendingProposal.into());
+        }
+        main_state.pending_config = None;
+        emit_cpi!(ConfigQueued {
+            main_state: main_state.key(),
+            pending_config: None,
+        });
+        Ok(())
+    }
+
+    /**
+     * Applies the queued fee, bounty, role and delay changes once their delay has passed, callable by anyone.
+     */
+    pub fn apply_pending_config(ctx: Context<ApplyPendingConfig>) -> Result<()> {
+        let main_state = &mut ctx.accounts.main_state;
+        let old_fee_schedule = main_state.fee_schedule;
+        main_state.apply_pending_config(ctx.accounts.clock.unix_timestamp * 1000)?;
+        if main_state.fee_schedule != old_fee_schedule {
+            emit_cpi!(FeesUpdated {
+                main_state: main_state.key(),
+                old_fee_schedule,
+                new_fee_schedule: main_state.fee_schedule,
+            });
+        }
+        emit_cpi!(ConfigApplied {
+            main_state: main_state.key(),
+            fee_schedule: main_state.fee_schedule,
+            roles: main_state.roles,
+            config_delay: main_state.config_delay,
+            renew_bounty_lamports: main_state.renew_bounty_lamports,
+        });
+        Ok(())
+    }
+
+    /**
+     * Replaces the paused instruction groups with the given mask, zero resumes everything.
+     */
+    pub fn set_pause(ctx: Context<SetPause>, paused: u16) -> Result<()> {
+        msg!("Params: {:?}", paused);
+        let main_state = &mut ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key && !main_state.has_role(Role::Pauser, ctx.accounts.signer.key) {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        if paused & !PAUSE_ALL != 0 {
+            return Err(MainStateError::InvalidPauseMask.into());
+        }
+        main_state.paused = paused;
+        emit_cpi!(PauseSet {
+            main_state: main_state.key(),
+            paused,
+        });
+        Ok(())
+    }
+
+    pub fn set_payout_address(ctx: Context<SetPayoutAddress>, payout_address: Pubkey) -> Result<()> {
+        msg!("Params: {:?}", payout_address);
+        let main_state = &mut ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        main_state.payout_address = payout_address;
+        emit_cpi!(PayoutAddressSet {
+            main_state: main_state.key(),
+            payout_address,
+        });
+        Ok(())
+    }
+
+    /**
+     * Sets the key signing the discount coupons, None stops accepting any coupons.
+     */
+    pub fn set_coupon_signer(ctx: Context<SetCouponSigner>, coupon_signer: Option<Pubkey>) -> Result<()> {
+        msg!("Params: {:?}", coupon_signer);
+        let main_state = &mut ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        main_state.coupon_signer = coupon_signer;
+        emit_cpi!(CouponSignerSet {
+            main_state: main_state.key(),
+            coupon_signer,
+        });
+        Ok(())
+    }
+
+    /**
+     * Sets how long the access is kept after the end of the renewable period, so the keeper can still renew it.
+     */
+    pub fn set_grace_period(ctx: Context<SetGracePeriod>, grace_period: i64) -> Result<()> {
+        msg!("Params: {:?}", grace_period);
+        let main_state = &mut ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        if grace_period < 0 {
+            return Err(MainStateError::InvalidGracePeriod.into());
+        }
+        main_state.grace_period = grace_period;
+        emit_cpi!(GracePeriodSet {
+            main_state: main_state.key(),
+            grace_period,
+        });
+        Ok(())
+    }
+}
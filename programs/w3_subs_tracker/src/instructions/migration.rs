@@ -0,0 +1,211 @@
+use anchor_lang::prelude::*;
+use crate::state::main_state::MainState;
+use crate::state::plan::Plan;
+use crate::state::treasury::Treasury;
+
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct MigrateMainState<'info> {
+    // legacy main state, checked and deserialized in the instruction
+    /// CHECK: Owner, discriminator and the legacy space are checked in the instruction
+    #[account(mut)]
+    pub main_state: UncheckedAccount<'info>,
+    #[account(init, payer = signer, space = 8 + Treasury::INIT_SPACE, seeds = [b"treasury", main_state.key().as_ref()], bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct MigrateSubscription<'info> {
+    // legacy subscription, checked and deserialized in the instruction
+    /// CHECK: Owner, discriminator and the legacy space are checked in the instruction
+    #[account(mut)]
+    pub subscription: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    // plans replacing the legacy subscription types, Pubkey::default() is stored when not provided
+    pub current_plan: Option<Account<'info, Plan>>,
+    pub desired_plan: Option<Account<'info, Plan>>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+pub mod processor {
+    use anchor_lang::system_program::{self, Transfer};
+    use anchor_lang::Discriminator;
+    use crate::errors::error::{MainStateError, SubscriptionError};
+    use crate::events::event::*;
+    use crate::instructions::subscription::processor::check_plan;
+    use crate::state::legacy::*;
+    use crate::state::main_state::{FeeSchedule, Roles, MAIN_STATE_VERSION, MIN_CONFIG_DELAY};
+    use crate::state::subscription::*;
+
+    use super::*;
+
+    /**
+     * Reallocates the account to the new space, the payer tops up the rent exempt balance of the new space.
+     */
+    fn grow<'info>(account: &AccountInfo<'info>, payer: &Signer<'info>, system_program: &Program<'info, System>, space: usize) -> Result<()> {
+        let top_up = Rent::get()?.minimum_balance(space).saturating_sub(account.lamports());
+        if top_up > 0 {
+            let cpi_accounts = Transfer {
+                from: payer.to_account_info(),
+                to: account.clone(),
+            };
+            system_program::transfer(CpiContext::new(system_program.to_account_info(), cpi_accounts), top_up)?;
+        }
+        account.realloc(space, false)?;
+        Ok(())
+    }
+
+    /**
+     * Upgrades the legacy main state in place: the percent unsubscribe fee becomes the fee schedule,
+     * the roles and the payout address are given to the owner and the treasury is created.
+     * The legacy subscriptions are counted as open once each of them is migrated.
+     */
+    pub fn migrate_main_state(ctx: Context<MigrateMainState>) -> Result<()> {
+        let account = ctx.accounts.main_state.to_account_info();
+        if account.owner != ctx.program_id {
+            return Err(ErrorCode::AccountOwnedByWrongProgram.into());
+        }
+        let legacy: LegacyMainState = read_legacy(&account.try_borrow_data()?, MainState::DISCRIMINATOR, LEGACY_MAIN_STATE_SPACE)?;
+        if legacy.owner != ctx.accounts.signer.key() {
+            return Err(SubscriptionError::InvalidOwner.into());
+        }
+        if legacy.unsubscribe_fee > 100 {
+            return Err(MainStateError::InvalidFeeSchedule.into());
+        }
+        // legacy fee was taken in full from the refund paid out and in half from the refund kept as credits
+        let fee_schedule = FeeSchedule {
+            early_termination_bps: legacy.unsubscribe_fee as u16 * 100,
+            downgrade_bps: legacy.unsubscribe_fee as u16 * 50,
+            ..FeeSchedule::default()
+        };
+        let (_, bump) = Pubkey::find_program_address(&[b"mainState"], ctx.program_id);
+        let main_state = MainState {
+            version: MAIN_STATE_VERSION,
+            owner: legacy.owner,
+            authority: legacy.authority,
+            fee_schedule,
+            tenant_id: 0,
+            bump,
+            renew_bounty_lamports: 0,
+            accepted_mint: None,
+            pending_owner: None,
+            pending_authority: None,
+            multisig: None,
+            roles: Roles {
+                verifier: Some(legacy.authority),
+                treasurer: Some(legacy.owner),
+                fee_manager: Some(legacy.owner),
+                pauser: Some(legacy.owner),
+            },
+            paused: 0,
+            payout_address: legacy.owner,
+            config_delay: MIN_CONFIG_DELAY,
+            pending_config: None,
+            sunset_at: None,
+            open_subscriptions: 0,
+            coupon_signer: None,
+            referral_bps: 0,
+            grace_period: 0,
+        };
+        grow(&account, &ctx.accounts.signer, &ctx.accounts.system_program, 8 + MainState::INIT_SPACE)?;
+        main_state.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.main_state = account.key();
+        treasury.bump = ctx.bumps.treasury;
+        emit_cpi!(MainStateMigrated {
+            main_state: account.key(),
+            from_version: 0,
+            to_version: MAIN_STATE_VERSION,
+        });
+        Ok(())
+    }
+
+    /**
+     * Upgrades the legacy subscription in place: the credits stay credits, the used lamports of the running period become locked
+     * and the withdrawable rest of the balance becomes earned. The subscription types are replaced by the given plans.
+     * From now on the subscription is counted as open by the main state.
+     */
+    pub fn migrate_subscription(ctx: Context<MigrateSubscription>) -> Result<()> {
+        let account = ctx.accounts.subscription.to_account_info();
+        if account.owner != ctx.program_id {
+            return Err(ErrorCode::AccountOwnedByWrongProgram.into());
+        }
+        let legacy: LegacySubscription = read_legacy(&account.try_borrow_data()?, Subscription::DISCRIMINATOR, LEGACY_SUBSCRIPTION_SPACE)?;
+        let main_state = &ctx.accounts.main_state;
+        if legacy.imutable_initialized.main_state_pda != main_state.key() {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if main_state.authority != ctx.accounts.authority.key() {
+            return Err(SubscriptionError::InvalidAuthority.into());
+        }
+        let plan_key = |plan: &Option<Account<Plan>>| -> Result<Pubkey> {
+            match plan {
+                Some(plan) => {
+                    check_plan(plan, &main_state.key())?;
+                    Ok(plan.key())
+                },
+                None => Ok(Pubkey::default()),
+            }
+        };
+        let current_plan = plan_key(&ctx.accounts.current_plan)?;
+        let desired_plan = plan_key(&ctx.accounts.desired_plan)?;
+
+        let now = ctx.accounts.clock.unix_timestamp * 1000;
+        let credits = legacy.subscription_status_writable.after_verify_credit_lamports;
+        let locked = if legacy.authority_writable.valid_till > now { legacy.authority_writable.used_lamports } else { 0 };
+        // same as the legacy withdrawable amount, computed before the rent top up
+        let earned = account.lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(LEGACY_SUBSCRIPTION_SPACE))
+            .saturating_sub(credits)
+            .saturating_sub(locked);
+        let mut subscription = Subscription {
+            version: SUBSCRIPTION_VERSION,
+            imutable_initialized: MutableInitialized {
+                main_state_pda: legacy.imutable_initialized.main_state_pda,
+                user: legacy.imutable_initialized.user,
+                referrer: None,
+            },
+            subscription_status_writable: CurrentSubscriptionStatistics {
+                after_verify_utc_timestamp: legacy.subscription_status_writable.after_verify_utc_timestamp,
+                desired_plan,
+                auto_renew: false,
+            },
+            authority_writable: AuthorityWritable {
+                current_plan,
+                valid_till: legacy.authority_writable.valid_till,
+            },
+            ledger: Ledger {
+                credits,
+                locked,
+                earned,
+                ..Ledger::default()
+            },
+            fee_schedule: main_state.fee_schedule,
+            trial_used: false,
+            // the running or ended period is settled to the status of now
+            status: if legacy.authority_writable.valid_till > 0 { SubscriptionStatus::Active } else { SubscriptionStatus::Pending },
+            usage: Usage::default(),
+        };
+        subscription.settle(now, main_state.grace_period)?;
+        grow(&account, &ctx.accounts.authority, &ctx.accounts.system_program, 8 + Subscription::INIT_SPACE)?;
+        subscription.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+        let main_state = &mut ctx.accounts.main_state;
+        main_state.open_subscriptions = main_state.open_subscriptions.checked_add(1).ok_or(SubscriptionError::AirthemticError)?;
+        emit_cpi!(SubscriptionMigrated {
+            subscription: account.key(),
+            main_state: main_state.key(),
+            from_version: 0,
+            to_version: SUBSCRIPTION_VERSION,
+        });
+        Ok(())
+    }
+}
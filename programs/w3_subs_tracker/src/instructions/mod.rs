@@ -1,2 +1,10 @@
+pub mod coupon;
 pub mod main_state;
-pub mod subscription;
\ No newline at end of file
+pub mod migration;
+pub mod plan;
+pub mod referral;
+pub mod subscription;
+pub mod sunset;
+pub mod treasury;
+pub mod trial;
+pub mod usage;
@@ -0,0 +1,173 @@
+use anchor_lang::prelude::*;
+use crate::state::main_state::{MainState, PAUSE_PLANS};
+use crate::state::plan::*;
+
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(plan_id: u64)]
+pub struct CreatePlan<'info> {
+    #[account(init, payer = signer, space = 8 + Plan::INIT_SPACE, seeds = [b"plan", main_state.key().as_ref(), plan_id.to_le_bytes().as_ref()], bump)]
+    pub plan: Account<'info, Plan>,
+    pub main_state: Account<'info, MainState>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdatePlan<'info> {
+    #[account(mut, seeds = [b"plan", main_state.key().as_ref(), plan.plan_id.to_le_bytes().as_ref()], bump = plan.bump)]
+    pub plan: Account<'info, Plan>,
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+}
+
+pub mod processor {
+    use crate::errors::error::PlanError;
+    use crate::events::event::*;
+
+    use super::*;
+
+    fn validate(name: &str, period: i64) -> Result<()> {
+        if name.len() > MAX_PLAN_NAME_LEN {
+            return Err(PlanError::NameTooLong.into());
+        }
+        if period <= 0 {
+            return Err(PlanError::InvalidPeriod.into());
+        }
+        Ok(())
+    }
+
+    pub fn create_plan(ctx: Context<CreatePlan>, plan_id: u64, name: String, price_lamports: u64, period: i64, tier: u8) -> Result<()> {
+        msg!("Params: {:?}, {:?}, {:?}, {:?}, {:?}", plan_id, name, price_lamports, period, tier);
+        ctx.accounts.main_state.check_not_paused(PAUSE_PLANS)?;
+        if ctx.accounts.main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        validate(&name, period)?;
+        let plan = &mut ctx.accounts.plan;
+        plan.main_state = ctx.accounts.main_state.key();
+        plan.plan_id = plan_id;
+        plan.name = name;
+        plan.price_lamports = price_lamports;
+        plan.period = period;
+        plan.tier = tier;
+        plan.active = true;
+        plan.bump = ctx.bumps.plan;
+        emit_cpi!(PlanCreated {
+            main_state: plan.main_state,
+            plan: plan.key(),
+            plan_id,
+            price_lamports: plan.price_lamports,
+            period: plan.period,
+            tier: plan.tier,
+        });
+        Ok(())
+    }
+
+    /**
+     * Changes of the price or period are applied only to the periods started after the update.
+     */
+    pub fn update_plan(ctx: Context<UpdatePlan>, name: Option<String>, price_lamports: Option<u64>, period: Option<i64>, tier: Option<u8>) -> Result<()> {
+        msg!("Params: {:?}, {:?}, {:?}, {:?}", name, price_lamports, period, tier);
+        ctx.accounts.main_state.check_not_paused(PAUSE_PLANS)?;
+        if ctx.accounts.main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        let plan = &mut ctx.accounts.plan;
+        if !plan.active {
+            return Err(PlanError::PlanRetired.into());
+        }
+        validate(name.as_deref().unwrap_or(&plan.name), period.unwrap_or(plan.period))?;
+        if let Some(name) = name {
+            plan.name = name;
+        }
+        if let Some(price_lamports) = price_lamports {
+            plan.price_lamports = price_lamports;
+        }
+        if let Some(period) = period {
+            plan.period = period;
+        }
+        if let Some(tier) = tier {
+            plan.tier = tier;
+        }
+        emit_cpi!(PlanUpdated {
+            main_state: plan.main_state,
+            plan: plan.key(),
+            price_lamports: plan.price_lamports,
+            period: plan.period,
+            tier: plan.tier,
+        });
+        Ok(())
+    }
+
+    pub fn retire_plan(ctx: Context<UpdatePlan>) -> Result<()> {
+        ctx.accounts.main_state.check_not_paused(PAUSE_PLANS)?;
+        if ctx.accounts.main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        let plan = &mut ctx.accounts.plan;
+        if !plan.active {
+            return Err(PlanError::PlanRetired.into());
+        }
+        plan.active = false;
+        emit_cpi!(PlanRetired {
+            main_state: plan.main_state,
+            plan: plan.key(),
+        });
+        Ok(())
+    }
+
+    /**
+     * Sets the trial length of the plan, 0 stops offering the trial. Trials already running are not affected.
+     */
+    pub fn set_plan_trial(ctx: Context<UpdatePlan>, trial_period: i64) -> Result<()> {
+        msg!("Params: {:?}", trial_period);
+        ctx.accounts.main_state.check_not_paused(PAUSE_PLANS)?;
+        if ctx.accounts.main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        let plan = &mut ctx.accounts.plan;
+        if !plan.active {
+            return Err(PlanError::PlanRetired.into());
+        }
+        if trial_period < 0 {
+            return Err(PlanError::InvalidPeriod.into());
+        }
+        plan.trial_period = trial_period;
+        emit_cpi!(PlanTrialSet {
+            main_state: plan.main_state,
+            plan: plan.key(),
+            trial_period,
+        });
+        Ok(())
+    }
+
+    /**
+     * Sets the unit price of the metered usage and the optional cap of the units per period, zero unit price stops the metering.
+     * Unlike the price of the period, the new unit price applies to all the usage recorded after the update.
+     */
+    pub fn set_plan_metering(ctx: Context<UpdatePlan>, unit_price: u64, usage_cap: Option<u64>) -> Result<()> {
+        msg!("Params: {:?}, {:?}", unit_price, usage_cap);
+        ctx.accounts.main_state.check_not_paused(PAUSE_PLANS)?;
+        if ctx.accounts.main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        let plan = &mut ctx.accounts.plan;
+        if !plan.active {
+            return Err(PlanError::PlanRetired.into());
+        }
+        plan.unit_price = unit_price;
+        plan.usage_cap = usage_cap;
+        emit_cpi!(PlanMeteringSet {
+            main_state: plan.main_state,
+            plan: plan.key(),
+            unit_price,
+            usage_cap,
+        });
+        Ok(())
+    }
+}
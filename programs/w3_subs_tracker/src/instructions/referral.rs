@@ -0,0 +1,135 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+use crate::state::main_state::MainState;
+use crate::state::referral::ReferrerBalance;
+use crate::state::treasury::Treasury;
+
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetReferralBps<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(mut, seeds = [b"referrer", main_state.key().as_ref(), referrer.key().as_ref()], bump = referrer_balance.bump)]
+    pub referrer_balance: Account<'info, ReferrerBalance>,
+    pub main_state: Account<'info, MainState>,
+    #[account(mut, seeds = [b"treasury", main_state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Treasury vault PDA, unpacked in the instruction
+    #[account(mut, seeds = [b"treasury_vault", main_state.key().as_ref()], bump)]
+    pub treasury_vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: Unpacked and validated against the accepted mint in the instruction
+    #[account(mut)]
+    pub referrer_token_account: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+pub mod processor {
+    use crate::errors::error::{MainStateError, SubscriptionError};
+    use crate::events::event::*;
+    use crate::state::main_state::{Role, PAUSE_TREASURY};
+    use crate::state::subscription::Subscription;
+    use crate::utils::funds::{create_pda_account, Funds};
+    use crate::utils::math;
+
+    use super::*;
+
+    /**
+     * Creates the balance PDA of the referrer unless it already exists, paid by the referred user. Lamports sent to the address beforehand
+     * can't block it.
+     */
+    pub(crate) fn create_referrer_balance<'info>(balance: &AccountInfo<'info>, main_state: &Pubkey, referrer: &Pubkey, payer: &Signer<'info>, system_program: &Program<'info, System>, program_id: &Pubkey) -> Result<()> {
+        let (address, bump) = Pubkey::find_program_address(&[b"referrer", main_state.as_ref(), referrer.as_ref()], program_id);
+        if balance.key() != address {
+            return Err(ErrorCode::ConstraintSeeds.into());
+        }
+        if balance.owner == program_id {
+            return Ok(());
+        }
+        let signer_seeds: &[&[&[u8]]] = &[&[b"referrer", main_state.as_ref(), referrer.as_ref(), &[bump]]];
+        create_pda_account(payer, balance, signer_seeds, 8 + ReferrerBalance::INIT_SPACE, program_id, system_program)?;
+        let referrer_balance = ReferrerBalance {
+            main_state: *main_state,
+            referrer: *referrer,
+            accrued: 0,
+            claimed: 0,
+            bump,
+        };
+        referrer_balance.try_serialize(&mut &mut balance.try_borrow_mut_data()?[..])
+    }
+
+    /**
+     * Accounts the earned amount moved from the subscription to the treasury, accruing the referral share to the referrer of the subscription.
+     * Returns the referral share.
+     */
+    pub(crate) fn collect_earned(main_state: &MainState, subscription: &Subscription, treasury: &mut Treasury, referrer_balance: Option<&mut Account<ReferrerBalance>>, amount: u64) -> Result<u64> {
+        let share = match subscription.imutable_initialized.referrer {
+            Some(referrer) if main_state.referral_bps > 0 => {
+                let referrer_balance = referrer_balance.ok_or(SubscriptionError::MissingObligatoryAccount)?;
+                if referrer_balance.referrer != referrer || referrer_balance.main_state != subscription.imutable_initialized.main_state_pda {
+                    return Err(ErrorCode::ConstraintSeeds.into());
+                }
+                let share = math::fee_bps(amount, main_state.referral_bps, 0)?;
+                referrer_balance.accrued = referrer_balance.accrued.checked_add(share).ok_or(SubscriptionError::AirthemticError)?;
+                share
+            },
+            _ => 0,
+        };
+        treasury.collected = treasury.collected.checked_add(amount - share).ok_or(SubscriptionError::AirthemticError)?;
+        treasury.referral_owed = treasury.referral_owed.checked_add(share).ok_or(SubscriptionError::AirthemticError)?;
+        Ok(share)
+    }
+
+    /**
+     * Sets the share of the withdrawn debits accrued to the referrers. It only affects the treasury revenue, so it is not delayed.
+     */
+    pub fn set_referral_bps(ctx: Context<SetReferralBps>, referral_bps: u16) -> Result<()> {
+        msg!("Params: {:?}", referral_bps);
+        let main_state = &mut ctx.accounts.main_state;
+        if !main_state.has_role(Role::FeeManager, ctx.accounts.signer.key) {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        if referral_bps as u64 > math::BPS_DENOMINATOR {
+            return Err(MainStateError::InvalidFeeSchedule.into());
+        }
+        main_state.referral_bps = referral_bps;
+        emit_cpi!(ReferralBpsSet {
+            main_state: main_state.key(),
+            referral_bps,
+        });
+        Ok(())
+    }
+
+    /**
+     * Pays the accrued and not yet claimed referral rewards from the treasury to the referrer.
+     */
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        ctx.accounts.main_state.check_not_paused(PAUSE_TREASURY)?;
+        let referrer_balance = &mut ctx.accounts.referrer_balance;
+        let amount = referrer_balance.accrued.checked_sub(referrer_balance.claimed).ok_or(SubscriptionError::AirthemticError)?;
+        referrer_balance.claimed = referrer_balance.accrued;
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.referral_owed = treasury.referral_owed.checked_sub(amount).ok_or(SubscriptionError::AirthemticError)?;
+        let funds = Funds::resolve(&ctx.accounts.main_state, &ctx.accounts.treasury_vault, &ctx.accounts.token_program)?;
+        let main_state_key = ctx.accounts.main_state.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", main_state_key.as_ref(), &[treasury.bump]]];
+        funds.pay_out(&treasury.to_account_info(), signer_seeds, &ctx.accounts.referrer.to_account_info(), ctx.accounts.referrer_token_account.as_ref(), amount)?;
+        emit_cpi!(ReferralRewardsClaimed {
+            main_state: main_state_key,
+            referrer: ctx.accounts.referrer.key(),
+            amount,
+        });
+        Ok(())
+    }
+}
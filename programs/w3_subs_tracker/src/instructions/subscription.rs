@@ -1,22 +1,43 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
 use crate::state::subscription::*;
 use crate::state::main_state::*;
+use crate::state::plan::Plan;
+use crate::state::referral::ReferrerBalance;
+use crate::state::treasury::Treasury;
 
 
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct CreateSubscription<'info> {
-    #[account(init, payer = user, space = 64 + 17 + 17 + 8,  seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    #[account(init, payer = user, space = 8 + Subscription::INIT_SPACE,  seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
     pub subscription: Account<'info, Subscription>,
     #[account(mut)]
     pub main_state: Account<'info, MainState>,
+    pub plan: Account<'info, Plan>,
     #[account(mut)]
     pub user: Signer<'info>,
     pub system_program: Program<'info, System>,
     pub clock: Sysvar<'info, Clock>,
-    
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Validated to be the accepted mint in the instruction
+    pub mint: Option<UncheckedAccount<'info>>,
+    /// CHECK: Vault token account owned by the subscription PDA, created in the instruction
+    #[account(mut, seeds = [b"vault", subscription.key().as_ref()], bump)]
+    pub vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: Unpacked and validated against the accepted mint in the instruction
+    #[account(mut)]
+    pub user_token_account: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub rent: Sysvar<'info, Rent>,
+    // balance PDA of the referrer, obligatory only with the referrer, created in the instruction when missing
+    /// CHECK: Validated against the referrer balance PDA address in the instruction
+    #[account(mut)]
+    pub referrer_balance: Option<UncheckedAccount<'info>>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct SetSubscriptionDate<'info> {
     #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
@@ -27,9 +48,11 @@ pub struct SetSubscriptionDate<'info> {
     pub user: AccountInfo<'info>,
     pub system_program: Program<'info, System>,
     pub main_state: Account<'info, MainState>,
+    pub plan: Option<Account<'info, Plan>>,
     pub clock: Sysvar<'info, Clock>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Unsubscribe<'info> {
     #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
@@ -38,14 +61,26 @@ pub struct Unsubscribe<'info> {
     pub user: Signer<'info>,
     #[account(mut)]
     pub to_account: Option<SystemAccount<'info>>,
-    /// CHECK: This is not dangerous, we are using main_state_owner as creditor for refund after user refund
-    #[account(mut)]
-    pub main_state_owner: AccountInfo<'info>,
+    #[account(mut, seeds = [b"treasury", main_state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
     pub system_program: Program<'info, System>,
     pub main_state: Account<'info, MainState>,
+    pub new_desired_plan: Option<Account<'info, Plan>>,
     pub clock: Sysvar<'info, Clock>,
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Vault PDA of the subscription, unpacked in the instruction
+    #[account(mut, seeds = [b"vault", subscription.key().as_ref()], bump)]
+    pub vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: Unpacked and validated against the accepted mint in the instruction
+    #[account(mut)]
+    pub to_token_account: Option<UncheckedAccount<'info>>,
+    /// CHECK: Treasury vault PDA, validated against the accepted mint in the instruction
+    #[account(mut, seeds = [b"treasury_vault", main_state.key().as_ref()], bump)]
+    pub treasury_vault: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct FundSubcription<'info> {
     #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
@@ -55,83 +90,295 @@ pub struct FundSubcription<'info> {
     pub system_program: Program<'info, System>,
     pub main_state: Account<'info, MainState>,
     pub clock: Sysvar<'info, Clock>,
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Vault PDA of the subscription, unpacked in the instruction
+    #[account(mut, seeds = [b"vault", subscription.key().as_ref()], bump)]
+    pub vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: Unpacked and validated against the accepted mint in the instruction
+    #[account(mut)]
+    pub user_token_account: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct WithdrawFromSubcription<'info> {
     #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
     pub subscription: Account<'info, Subscription>,
     #[account(signer)]
-    pub authority: Signer<'info>,
+    pub treasurer: Signer<'info>,
     #[account(mut)]
     pub main_state: Account<'info, MainState>,
     pub clock: Sysvar<'info, Clock>,
     /// CHECK: This is not dangerous because we use given account for seeds purposes
     pub user: AccountInfo<'info>,
-    /// CHECK: This is not dangerous because we only deposit to the given account
-    #[account(mut)]
-    pub to_account: SystemAccount<'info>,
+    #[account(mut, seeds = [b"treasury", main_state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
     pub system_program: Program<'info, System>,
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Vault PDA of the subscription, unpacked in the instruction
+    #[account(mut, seeds = [b"vault", subscription.key().as_ref()], bump)]
+    pub vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: Treasury vault PDA, validated against the accepted mint in the instruction
+    #[account(mut, seeds = [b"treasury_vault", main_state.key().as_ref()], bump)]
+    pub treasury_vault: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+    // balance PDA of the referrer, obligatory only for the referred subscription
+    #[account(mut)]
+    pub referrer_balance: Option<Account<'info, ReferrerBalance>>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct ChangeSubscriptionType<'info> {
     #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
     pub subscription: Account<'info, Subscription>,
     #[account(mut)]
     pub main_state: Account<'info, MainState>,
+    pub plan: Account<'info, Plan>,
     #[account(signer)]
     pub user: SystemAccount<'info>,
     pub system_program: Program<'info, System>,
 }
 
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ActivateSubscription<'info> {
+    #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    pub main_state: Account<'info, MainState>,
+    pub plan: Account<'info, Plan>,
+    pub user: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ChangePlan<'info> {
+    #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    pub main_state: Account<'info, MainState>,
+    // plan of the running period
+    pub current_plan: Account<'info, Plan>,
+    pub new_plan: Account<'info, Plan>,
+    pub user: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetAutoRenew<'info> {
+    #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    pub main_state: Account<'info, MainState>,
+    pub user: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetCancelAtPeriodEnd<'info> {
+    #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    pub main_state: Account<'info, MainState>,
+    pub user: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetSuspended<'info> {
+    #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    pub main_state: Account<'info, MainState>,
+    /// CHECK: This is not dangerous because we only use given account to access correct PDA
+    pub user: AccountInfo<'info>,
+    pub authority: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyAccess<'info> {
+    #[account(seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    pub main_state: Account<'info, MainState>,
+    // current plan of the subscription
+    pub plan: Account<'info, Plan>,
+    /// CHECK: This is not dangerous because we only use given account to access correct PDA
+    pub user: AccountInfo<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RenewSubscription<'info> {
+    #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    pub main_state: Account<'info, MainState>,
+    pub plan: Account<'info, Plan>,
+    /// CHECK: This is not dangerous because we only use given account to access correct PDA
+    pub user: AccountInfo<'info>,
+    // anyone can crank the renewal, receiving the renew bounty
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Vault PDA of the subscription, unpacked in the instruction
+    #[account(mut, seeds = [b"vault", subscription.key().as_ref()], bump)]
+    pub vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: Unpacked and validated against the accepted mint in the instruction
+    #[account(mut)]
+    pub keeper_token_account: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct EmergencyWithdrawCredits<'info> {
+    #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub main_state: Account<'info, MainState>,
+    pub system_program: Program<'info, System>,
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Vault PDA of the subscription, unpacked in the instruction
+    #[account(mut, seeds = [b"vault", subscription.key().as_ref()], bump)]
+    pub vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: Unpacked and validated against the accepted mint in the instruction
+    #[account(mut)]
+    pub user_token_account: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CloseSubscription<'info> {
+    #[account(mut, close = user, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(mut, seeds = [b"treasury", main_state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Vault PDA of the subscription, unpacked in the instruction
+    #[account(mut, seeds = [b"vault", subscription.key().as_ref()], bump)]
+    pub vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: Treasury vault PDA, validated against the accepted mint in the instruction
+    #[account(mut, seeds = [b"treasury_vault", main_state.key().as_ref()], bump)]
+    pub treasury_vault: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+    // balance PDA of the referrer, obligatory only for the referred subscription
+    #[account(mut)]
+    pub referrer_balance: Option<Account<'info, ReferrerBalance>>,
+}
 
 pub mod processor {
     
 
-    use anchor_lang::system_program::{self, Transfer};
-    use solana_program::native_token::LAMPORTS_PER_SOL;
 
-    use crate::{errors::error::SubscriptionError};
+    use crate::errors::error::{MainStateError, PlanError, SubscriptionError};
+    use crate::instructions::referral::processor::{collect_earned, create_referrer_balance};
+    use crate::utils::funds::{create_vault, Funds};
+    use crate::utils::math;
+    use crate::events::event::*;
 
     use super::*;
 
     /**
-     * Create a new subcrtiption, meaning initial deposit is made by the user without setting up a subcription date. 
-     * This will be done in a separate instruction by the BE
+     * Plan can be chosen only if it belongs to the same main state and it is not retired.
      */
-    pub fn create_subscription(ctx: Context<CreateSubscription>, initial_deposit: u64, account_type: SubscriptionType) -> Result<()> {
-        msg!("Params: {:?}, {:?}", initial_deposit, account_type);
-        let subscription = &mut ctx.accounts.subscription;
-        if initial_deposit == 0 {
-            return Err(SubscriptionError::InvalidInitialDeposit.into());
+    pub(crate) fn check_plan(plan: &Plan, main_state: &Pubkey) -> Result<()> {
+        if plan.main_state != *main_state {
+            return Err(PlanError::IncorrectMainState.into());
         }
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.user.to_account_info(),
-            to: subscription.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.system_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        system_program::transfer(cpi_ctx, initial_deposit)?; 
-        let main_state = ctx.accounts.main_state.to_account_info().key;
-        let user = ctx.accounts.user.key;
+        if !plan.active {
+            return Err(PlanError::PlanRetired.into());
+        }
+        Ok(())
+    }
 
+    /**
+     * Fills the newly created subscription of the user with the given referrer, desired plan and credits, counting it in the main state.
+     */
+    pub(crate) fn initialize(subscription: &mut Subscription, main_state: &mut Account<MainState>, user: &Pubkey, referrer: Option<Pubkey>, desired_plan: Pubkey, now: i64, credits: u64) -> Result<()> {
+        main_state.open_subscriptions = main_state.open_subscriptions.checked_add(1).ok_or(SubscriptionError::AirthemticError)?;
+        subscription.version = SUBSCRIPTION_VERSION;
         subscription.authority_writable = AuthorityWritable {
-            current_account_type: SubscriptionType::FREE,
+            current_plan: Pubkey::default(),
             valid_till: 0,
-            used_lamports: 0,
         };
+        subscription.ledger = Ledger {
+            credits,
+            ..Ledger::default()
+        };
+        subscription.fee_schedule = main_state.fee_schedule;
 
         subscription.imutable_initialized = MutableInitialized {
-            main_state_pda: *main_state,
+            main_state_pda: main_state.key(),
             user: *user,
+            referrer,
         };
 
         subscription.subscription_status_writable = CurrentSubscriptionStatistics {
-            after_verify_credit_lamports: initial_deposit,
-            after_verify_utc_timestamp: ctx.accounts.clock.unix_timestamp * 1000,
-            desired_subscription_type: account_type,
+            after_verify_utc_timestamp: now,
+            desired_plan,
+            auto_renew: false,
         };
+        subscription.status = SubscriptionStatus::Pending;
+        Ok(())
+    }
+
+    /**
+     * Create a new subcrtiption, meaning initial deposit is made by the user without setting up a subcription date. 
+     * This will be done in a separate instruction by the BE
+     */
+    pub fn create_subscription(ctx: Context<CreateSubscription>, initial_deposit: u64, referrer: Option<Pubkey>) -> Result<()> {
+        msg!("Params: {:?}, {:?}, {:?}", initial_deposit, ctx.accounts.plan.key(), referrer);
+        ctx.accounts.main_state.check_not_paused(PAUSE_SUBSCRIBE)?;
+        ctx.accounts.main_state.check_not_sunset()?;
+        let subscription = &mut ctx.accounts.subscription;
+        if initial_deposit == 0 {
+            return Err(SubscriptionError::InvalidInitialDeposit.into());
+        }
+        // the referrer is given once, its balance is created with the first referred subscription
+        if let Some(referrer) = referrer {
+            if referrer == *ctx.accounts.user.key {
+                return Err(SubscriptionError::InvalidReferrer.into());
+            }
+            let referrer_balance = ctx.accounts.referrer_balance.as_ref().ok_or(SubscriptionError::MissingObligatoryAccount)?;
+            create_referrer_balance(referrer_balance, &ctx.accounts.main_state.key(), &referrer, &ctx.accounts.user, &ctx.accounts.system_program, ctx.program_id)?;
+        }
+        check_plan(&ctx.accounts.plan, &ctx.accounts.main_state.key())?;
+        // vault is created only for the main state with accepted mint, with the accepted mint
+        match (ctx.accounts.main_state.accepted_mint, &ctx.accounts.mint) {
+            (None, None) => Funds::Lamports.deposit(&ctx.accounts.user, None, &subscription.to_account_info(), &ctx.accounts.system_program, initial_deposit)?,
+            (Some(accepted_mint), Some(mint)) if accepted_mint == mint.key() => {
+                let (vault, token_program) = match (&ctx.accounts.vault, &ctx.accounts.token_program) {
+                    (Some(vault), Some(token_program)) => (vault, token_program),
+                    _ => return Err(SubscriptionError::MissingObligatoryAccount.into()),
+                };
+                let subscription_key = subscription.key();
+                let vault_seeds: &[&[u8]] = &[b"vault", subscription_key.as_ref(), &[ctx.bumps.vault.unwrap()]];
+                create_vault(&ctx.accounts.user, ctx.accounts.user_token_account.as_ref(), vault, vault_seeds, mint, &subscription.to_account_info(), &ctx.accounts.system_program, token_program, initial_deposit)?;
+            },
+            _ => return Err(SubscriptionError::InvalidMint.into()),
+        }
+        let main_state = ctx.accounts.main_state.to_account_info().key;
+        let user = ctx.accounts.user.key;
+        initialize(subscription, &mut ctx.accounts.main_state, user, referrer, ctx.accounts.plan.key(), ctx.accounts.clock.unix_timestamp * 1000, initial_deposit)?;
+        emit_cpi!(SubscriptionCreated {
+            subscription: subscription.key(),
+            main_state: *main_state,
+            user: *user,
+            desired_plan: ctx.accounts.plan.key(),
+            initial_deposit,
+        });
         Ok(())
     }
 
@@ -139,8 +386,9 @@ pub mod processor {
      * Setting desired subscription type for the user he wants to have.
      * Mainly applicable for the user who wants to change the subscription type for already existing subscription.
      */
-    pub fn change_desired_subscription_type(ctx: Context<ChangeSubscriptionType>, new_subscription_type: SubscriptionType) -> Result<()> {
-        msg!("Params: {:?}", new_subscription_type);
+    pub fn change_desired_subscription_type(ctx: Context<ChangeSubscriptionType>) -> Result<()> {
+        msg!("Params: {:?}", ctx.accounts.plan.key());
+        ctx.accounts.main_state.check_not_paused(PAUSE_SUBSCRIBE)?;
         let subscription = &mut ctx.accounts.subscription;
         if subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
             return Err(SubscriptionError::IncorrectMainState.into());
@@ -149,164 +397,602 @@ pub mod processor {
             return Err(SubscriptionError::InvalidAuthority.into());
         }
 
-        subscription.subscription_status_writable.desired_subscription_type = new_subscription_type;
+        check_plan(&ctx.accounts.plan, &ctx.accounts.main_state.key())?;
+
+        subscription.subscription_status_writable.desired_plan = ctx.accounts.plan.key();
+        emit_cpi!(DesiredTypeChanged {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            desired_plan: ctx.accounts.plan.key(),
+        });
         Ok(())
     }
 
     /**
-     * Setting the subscription date for the user, this can be done only by the BE pubkey (main_state.authority)
+     * Starts a new subscription period of the given plan, moving the given price (the plan price unless discounted) from credits to debits.
+     * The subscription becomes active, the fee schedule of the main state applies to the period.
      */
-    pub fn set_subscription_info(ctx: Context<SetSubscriptionDate>, subscription_date: Option<i64>, used_lamports: Option<u64>, subscription_type: Option<SubscriptionType>) -> Result<()> {
-        msg!("Params: {:?}, {:?}, {:?}", subscription_date, used_lamports, subscription_type);
-        match (&subscription_date, &used_lamports, &subscription_type) {
-            (None, None, None) => return Ok(()),
-            _ => (),
+    pub(crate) fn start_period(subscription: &mut Subscription, plan: &Account<Plan>, price: u64, main_state: &MainState, now: i64) -> Result<()> {
+        subscription.settle(now, main_state.grace_period)?;
+        subscription.transition(SubscriptionStatus::Active)?;
+        if subscription.ledger.credits < price {
+            return Err(SubscriptionError::NotEnoughCredits.into());
         }
+        subscription.ledger.credits -= price;
+        subscription.ledger.locked = price;
+        subscription.fee_schedule = main_state.fee_schedule;
+        subscription.usage = Usage {
+            period_start: now,
+            ..Usage::default()
+        };
+        subscription.subscription_status_writable.after_verify_utc_timestamp = now;
+        subscription.authority_writable = AuthorityWritable {
+            current_plan: plan.key(),
+            valid_till: now.checked_add(plan.period).ok_or(SubscriptionError::AirthemticError)?,
+        };
+        Ok(())
+    }
+
+    /**
+     * Self-service alternative to set_subscription_info, performing the readme "Backend Steps" on-chain:
+     * no active period, desired plan is valid, enough credits for the plan price.
+     */
+    pub fn activate_subscription(ctx: Context<ActivateSubscription>) -> Result<()> {
+        msg!("Params: {:?}", ctx.accounts.plan.key());
+        ctx.accounts.main_state.check_not_paused(PAUSE_ACTIVATE)?;
+        ctx.accounts.main_state.check_not_sunset()?;
         let subscription = &mut ctx.accounts.subscription;
-        // checks if initialized main_state PDA's pubkey is the same as the one passed as account
         if subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
             return Err(SubscriptionError::IncorrectMainState.into());
         }
-        let authority = &mut ctx.accounts.main_state.authority;
-        if authority != ctx.accounts.authority.key {
+        if &subscription.imutable_initialized.user.key() != ctx.accounts.user.key {
             return Err(SubscriptionError::InvalidAuthority.into());
         }
-        subscription.subscription_status_writable.after_verify_utc_timestamp = ctx.accounts.clock.unix_timestamp * 1000;
-        // subscription.subscription_status_writable.after_verify_credit_lamports = 
-        match used_lamports {
-            Some(lamports) => {
-                if subscription.subscription_status_writable.after_verify_credit_lamports < lamports {
-                    return Err(SubscriptionError::NotEnoughCredits.into());
-                }
-                subscription.subscription_status_writable.after_verify_credit_lamports -= lamports;
-                subscription.authority_writable.used_lamports = lamports;
-            },
-            None => (),
+        let now = ctx.accounts.clock.unix_timestamp * 1000;
+        if subscription.authority_writable.valid_till > now {
+            return Err(SubscriptionError::ActiveSubscription.into());
+        }
+        if subscription.subscription_status_writable.desired_plan != ctx.accounts.plan.key() {
+            return Err(PlanError::NotDesiredPlan.into());
+        }
+        check_plan(&ctx.accounts.plan, &ctx.accounts.main_state.key())?;
+        start_period(subscription, &ctx.accounts.plan, ctx.accounts.plan.price_lamports, &ctx.accounts.main_state, now)?;
+        emit_cpi!(SubscriptionActivated {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            plan: ctx.accounts.plan.key(),
+            valid_till: subscription.authority_writable.valid_till,
+            price_lamports: ctx.accounts.plan.price_lamports,
+        });
+        Ok(())
+    }
+
+    /**
+     * Switches the running period to the new plan without unsubscribing: the unused part of the period is credited back
+     * (fee-free for an upgrade to a higher tier, with the downgrade fee of the period otherwise) and a full period of the new plan is charged from the credits.
+     */
+    pub fn change_plan(ctx: Context<ChangePlan>) -> Result<()> {
+        msg!("Params: {:?}", ctx.accounts.new_plan.key());
+        ctx.accounts.main_state.check_not_paused(PAUSE_ACTIVATE)?;
+        ctx.accounts.main_state.check_not_sunset()?;
+        let subscription = &mut ctx.accounts.subscription;
+        if subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if &subscription.imutable_initialized.user.key() != ctx.accounts.user.key {
+            return Err(SubscriptionError::InvalidAuthority.into());
+        }
+        let (current_plan, new_plan) = (&ctx.accounts.current_plan, &ctx.accounts.new_plan);
+        if subscription.authority_writable.current_plan != current_plan.key() {
+            return Err(PlanError::NotCurrentPlan.into());
+        }
+        if new_plan.key() == current_plan.key() {
+            return Err(PlanError::SamePlan.into());
+        }
+        check_plan(new_plan, &ctx.accounts.main_state.key())?;
+        let now = ctx.accounts.clock.unix_timestamp * 1000;
+        subscription.settle(now, ctx.accounts.main_state.grace_period)?;
+        if subscription.authority_writable.valid_till <= now {
+            return Err(SubscriptionError::NoActiveSubscription.into());
+        }
+        // same proration as unsubscribe without withdrawal, the fees of the period apply
+        let unused = math::unused_part(
+            subscription.ledger.locked,
+            subscription.subscription_status_writable.after_verify_utc_timestamp,
+            subscription.authority_writable.valid_till,
+            now,
+        )?;
+        let period_fee_schedule = subscription.fee_schedule;
+        let fee = if new_plan.tier > current_plan.tier {
+            0
+        } else {
+            math::fee_bps(unused, period_fee_schedule.downgrade_bps, period_fee_schedule.min_downgrade_fee)?
         };
-        subscription.authority_writable.valid_till = match subscription_date {
-            Some(date) => date,
-            None => 0,
+        let ledger = &mut subscription.ledger;
+        ledger.earned = ledger.earned.checked_add(ledger.locked - unused).ok_or(SubscriptionError::AirthemticError)?;
+        ledger.fees_owed = ledger.fees_owed.checked_add(fee).ok_or(SubscriptionError::AirthemticError)?;
+        ledger.credits = ledger.credits.checked_add(unused - fee).ok_or(SubscriptionError::AirthemticError)?;
+        ledger.locked = 0;
+        // the new period starts right away, so the subscription stays active
+        start_period(subscription, new_plan, new_plan.price_lamports, &ctx.accounts.main_state, now)?;
+        subscription.subscription_status_writable.desired_plan = new_plan.key();
+        emit_cpi!(PlanChanged {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            from_plan: current_plan.key(),
+            to_plan: new_plan.key(),
+            credited: unused - fee,
+            fee,
+            price_lamports: new_plan.price_lamports,
+            valid_till: subscription.authority_writable.valid_till,
+        });
+        Ok(())
+    }
+
+    /**
+     * Opt in or out of the automatic renewal of the current plan.
+     */
+    pub fn set_auto_renew(ctx: Context<SetAutoRenew>, auto_renew: bool) -> Result<()> {
+        msg!("Params: {:?}", auto_renew);
+        ctx.accounts.main_state.check_not_paused(PAUSE_SUBSCRIBE)?;
+        let subscription = &mut ctx.accounts.subscription;
+        if subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if &subscription.imutable_initialized.user.key() != ctx.accounts.user.key {
+            return Err(SubscriptionError::InvalidAuthority.into());
+        }
+        subscription.settle(ctx.accounts.clock.unix_timestamp * 1000, ctx.accounts.main_state.grace_period)?;
+        // the cancelled period has to be resumed first
+        if auto_renew && subscription.status == SubscriptionStatus::CancelAtPeriodEnd {
+            return Err(SubscriptionError::InvalidStatusTransition.into());
+        }
+        subscription.subscription_status_writable.auto_renew = auto_renew;
+        emit_cpi!(AutoRenewSet {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            auto_renew,
+        });
+        Ok(())
+    }
+
+    /**
+     * Cancels the running period at its end (turning off the auto renewal) or resumes the cancelled one.
+     * The cancelled period keeps the access till its end, without the grace period.
+     */
+    pub fn set_cancel_at_period_end(ctx: Context<SetCancelAtPeriodEnd>, cancel: bool) -> Result<()> {
+        msg!("Params: {:?}", cancel);
+        ctx.accounts.main_state.check_not_paused(PAUSE_SUBSCRIBE)?;
+        let subscription = &mut ctx.accounts.subscription;
+        if subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if &subscription.imutable_initialized.user.key() != ctx.accounts.user.key {
+            return Err(SubscriptionError::InvalidAuthority.into());
+        }
+        subscription.settle(ctx.accounts.clock.unix_timestamp * 1000, ctx.accounts.main_state.grace_period)?;
+        let from = subscription.status;
+        match (from, cancel) {
+            (SubscriptionStatus::Active, true) => {
+                subscription.transition(SubscriptionStatus::CancelAtPeriodEnd)?;
+                subscription.subscription_status_writable.auto_renew = false;
+            },
+            (SubscriptionStatus::CancelAtPeriodEnd, false) => subscription.transition(SubscriptionStatus::Active)?,
+            _ => return Err(SubscriptionError::InvalidStatusTransition.into()),
+        }
+        emit_cpi!(StatusChanged {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            from,
+            to: subscription.status,
+        });
+        Ok(())
+    }
+
+    /**
+     * Suspends the subscription, blocking the access and new periods, or lifts the suspension.
+     * The lifted subscription is active again when its period is still running, expired otherwise.
+     */
+    pub fn set_suspended(ctx: Context<SetSuspended>, suspended: bool) -> Result<()> {
+        msg!("Params: {:?}", suspended);
+        ctx.accounts.main_state.check_not_paused(PAUSE_SET_INFO)?;
+        let subscription = &mut ctx.accounts.subscription;
+        if subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if !ctx.accounts.main_state.has_role(Role::Verifier, ctx.accounts.authority.key) {
+            return Err(SubscriptionError::InvalidAuthority.into());
+        }
+        let now = ctx.accounts.clock.unix_timestamp * 1000;
+        subscription.settle(now, ctx.accounts.main_state.grace_period)?;
+        let from = subscription.status;
+        if suspended {
+            subscription.transition(SubscriptionStatus::Suspended)?;
+        } else if from == SubscriptionStatus::Suspended {
+            subscription.status = if subscription.authority_writable.valid_till > now { SubscriptionStatus::Active } else { SubscriptionStatus::Expired };
+        } else {
+            return Err(SubscriptionError::InvalidStatusTransition.into());
+        }
+        emit_cpi!(StatusChanged {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            from,
+            to: subscription.status,
+        });
+        Ok(())
+    }
+
+    /**
+     * Renews the expired current plan of the auto renewing subscription from its credits, charging the plan price and the renew bounty.
+     * The bounty is transferred to the keeper calling the instruction, capped by MAX_RENEW_BOUNTY_BPS of the plan price.
+     */
+    pub fn renew(ctx: Context<RenewSubscription>) -> Result<()> {
+        ctx.accounts.main_state.check_not_paused(PAUSE_ACTIVATE)?;
+        ctx.accounts.main_state.check_not_sunset()?;
+        let subscription = &mut ctx.accounts.subscription;
+        if subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if !subscription.subscription_status_writable.auto_renew {
+            return Err(SubscriptionError::AutoRenewDisabled.into());
+        }
+        let now = ctx.accounts.clock.unix_timestamp * 1000;
+        if subscription.authority_writable.valid_till > now {
+            return Err(SubscriptionError::ActiveSubscription.into());
+        }
+        if subscription.authority_writable.current_plan != ctx.accounts.plan.key() {
+            return Err(PlanError::NotCurrentPlan.into());
+        }
+        check_plan(&ctx.accounts.plan, &ctx.accounts.main_state.key())?;
+        let max_bounty = math::mul_div(ctx.accounts.plan.price_lamports, MAX_RENEW_BOUNTY_BPS as u64, math::BPS_DENOMINATOR)?;
+        let bounty = ctx.accounts.main_state.renew_bounty_lamports.min(max_bounty);
+        let required = ctx.accounts.plan.price_lamports.checked_add(bounty).ok_or(SubscriptionError::AirthemticError)?;
+        if subscription.ledger.credits < required {
+            return Err(SubscriptionError::NotEnoughCredits.into());
+        }
+        start_period(subscription, &ctx.accounts.plan, ctx.accounts.plan.price_lamports, &ctx.accounts.main_state, now)?;
+        subscription.ledger.credits -= bounty;
+
+        let main_state_key = ctx.accounts.main_state.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"subscription", ctx.accounts.user.key.as_ref(), main_state_key.as_ref(), &[ctx.bumps.subscription]]];
+        let funds = Funds::resolve(&ctx.accounts.main_state, &ctx.accounts.vault, &ctx.accounts.token_program)?;
+        funds.pay_out(&subscription.to_account_info(), signer_seeds, &ctx.accounts.keeper.to_account_info(), ctx.accounts.keeper_token_account.as_ref(), bounty)?;
+        emit_cpi!(Renewed {
+            subscription: subscription.key(),
+            main_state: main_state_key,
+            plan: ctx.accounts.plan.key(),
+            keeper: ctx.accounts.keeper.key(),
+            valid_till: subscription.authority_writable.valid_till,
+            bounty_lamports: bounty,
+        });
+        Ok(())
+    }
+
+    /**
+     * Read only check of the running period (or its grace period) with the current plan of at least the given tier. Returns the timestamp the access ends.
+     */
+    pub fn verify_access(ctx: Context<VerifyAccess>, min_tier: u8) -> Result<i64> {
+        msg!("Params: {:?}", min_tier);
+        let subscription = &ctx.accounts.subscription;
+        if subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if subscription.authority_writable.current_plan != ctx.accounts.plan.key() {
+            return Err(PlanError::NotCurrentPlan.into());
+        }
+        if ctx.accounts.plan.main_state != ctx.accounts.main_state.key() {
+            return Err(PlanError::IncorrectMainState.into());
+        }
+        // the grace period extends the access of the ended period
+        let grace_period = ctx.accounts.main_state.grace_period;
+        let access_till = match subscription.status_at(ctx.accounts.clock.unix_timestamp * 1000, grace_period) {
+            SubscriptionStatus::Active | SubscriptionStatus::CancelAtPeriodEnd => subscription.authority_writable.valid_till,
+            SubscriptionStatus::Grace => subscription.authority_writable.valid_till.saturating_add(grace_period),
+            SubscriptionStatus::Suspended => return Err(SubscriptionError::SubscriptionSuspended.into()),
+            _ => return Err(SubscriptionError::NoActiveSubscription.into()),
         };
-        if subscription_type.is_some() {
-            subscription.authority_writable.current_account_type = subscription_type.unwrap();
+        if ctx.accounts.plan.tier < min_tier {
+            return Err(SubscriptionError::InsufficientTier.into());
+        }
+        Ok(access_till)
+    }
+
+    /**
+     * Setting the subscription date for the user, this can be done only by the BE pubkey (main_state.roles.verifier)
+     */
+    pub fn set_subscription_info(ctx: Context<SetSubscriptionDate>, subscription_date: Option<i64>, used_lamports: Option<u64>) -> Result<()> {
+        let plan = ctx.accounts.plan.as_ref().map(|plan| plan.key());
+        msg!("Params: {:?}, {:?}, {:?}", subscription_date, used_lamports, plan);
+        ctx.accounts.main_state.check_not_paused(PAUSE_SET_INFO)?;
+        ctx.accounts.main_state.check_not_sunset()?;
+        if let (None, None, None) = (&subscription_date, &used_lamports, &plan) {
+            return Ok(());
+        }
+        let subscription = &mut ctx.accounts.subscription;
+        // checks if initialized main_state PDA's pubkey is the same as the one passed as account
+        if subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if !ctx.accounts.main_state.has_role(Role::Verifier, ctx.accounts.authority.key) {
+            return Err(SubscriptionError::InvalidAuthority.into());
+        }
+        if let Some(plan) = &ctx.accounts.plan {
+            check_plan(plan, &ctx.accounts.main_state.key())?;
+        }
+        let now = ctx.accounts.clock.unix_timestamp * 1000;
+        subscription.settle(now, ctx.accounts.main_state.grace_period)?;
+        subscription.subscription_status_writable.after_verify_utc_timestamp = now;
+        if let Some(lamports) = used_lamports {
+            if subscription.ledger.credits < lamports {
+                return Err(SubscriptionError::NotEnoughCredits.into());
+            }
+            // debits of the replaced period are considered earned
+            subscription.ledger.earned = subscription.ledger.earned.checked_add(subscription.ledger.locked).ok_or(SubscriptionError::AirthemticError)?;
+            subscription.ledger.credits -= lamports;
+            subscription.ledger.locked = lamports;
+        }
+        subscription.authority_writable.valid_till = subscription_date.unwrap_or_default();
+        if subscription_date.is_some() {
+            subscription.fee_schedule = ctx.accounts.main_state.fee_schedule;
+            subscription.usage = Usage {
+                period_start: now,
+                ..Usage::default()
+            };
+        }
+        if let Some(plan) = plan {
+            subscription.authority_writable.current_plan = plan;
+        }
+        let status = if subscription.authority_writable.valid_till > now { SubscriptionStatus::Active } else { SubscriptionStatus::Expired };
+        // the subscription without any period stays pending, the suspended one stays suspended
+        match (subscription.status, status) {
+            (SubscriptionStatus::Suspended, _) | (SubscriptionStatus::Pending, SubscriptionStatus::Expired) => {},
+            _ => subscription.transition(status)?,
         }
+        emit_cpi!(InfoSet {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            current_plan: subscription.authority_writable.current_plan,
+            valid_till: subscription.authority_writable.valid_till,
+            locked: subscription.ledger.locked,
+        });
         Ok(())
     }
     /**
-     * Resend new SOL to the subscription account
+     * Resend new SOL (or accepted tokens) to the subscription account
      */
     pub fn fund_subscription(ctx: Context<FundSubcription>, new_deposit_lamports: u64) -> Result<()> {
         msg!("Params: {:?}", new_deposit_lamports);
+        ctx.accounts.main_state.check_not_paused(PAUSE_FUND)?;
+        ctx.accounts.main_state.check_not_sunset()?;
         let subscription = &mut ctx.accounts.subscription;
-        subscription.subscription_status_writable.after_verify_credit_lamports += new_deposit_lamports;
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.user.to_account_info(),
-            to: ctx.accounts.subscription.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.system_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        system_program::transfer(cpi_ctx, new_deposit_lamports)?;
+        subscription.ledger.credits = subscription.ledger.credits.checked_add(new_deposit_lamports).ok_or(SubscriptionError::AirthemticError)?;
+        let funds = Funds::resolve(&ctx.accounts.main_state, &ctx.accounts.vault, &ctx.accounts.token_program)?;
+        funds.deposit(&ctx.accounts.user, ctx.accounts.user_token_account.as_ref(), &subscription.to_account_info(), &ctx.accounts.system_program, new_deposit_lamports)?;
+        emit_cpi!(Funded {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            amount: new_deposit_lamports,
+            credits: subscription.ledger.credits,
+        });
         Ok(())
     }
     /**
      * Let the user owner unsubscribe from the subscription account.
      * Applicable if user wants to end whole subscription and gets its lamports or if he wants to change the subscription type.
+     * No fees are charged once the sunset of the main state started.
      */
-    pub fn unsubscribe(ctx: Context<Unsubscribe>, withdraw_content: bool, change_desired_subs_type: Option<SubscriptionType>) -> Result<()> {
-        msg!("Params: {:?}, {:?}", withdraw_content, change_desired_subs_type);
+    pub fn unsubscribe(ctx: Context<Unsubscribe>, withdraw_content: bool) -> Result<()> {
+        let new_desired_plan = ctx.accounts.new_desired_plan.as_ref().map(|plan| plan.key());
+        msg!("Params: {:?}, {:?}", withdraw_content, new_desired_plan);
+        ctx.accounts.main_state.check_not_paused(PAUSE_UNSUBSCRIBE)?;
         // checks for valid main_state account inserted in the context
         if ctx.accounts.subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
             return Err(SubscriptionError::IncorrectMainState.into());
         }
-        // check if the given owner of main state is the same as the one passed in the context
-        if ctx.accounts.main_state_owner.key() != ctx.accounts.main_state.owner.key() {
-            return Err(SubscriptionError::InvalidOwner.into());
-        }
         // check for authority who wants to unsubscribe given user
         if ctx.accounts.subscription.imutable_initialized.user != *ctx.accounts.user.key && ctx.accounts.main_state.authority != *ctx.accounts.user.key {
             return Err(SubscriptionError::InvalidAuthority.into());
         }
+        if let Some(plan) = &ctx.accounts.new_desired_plan {
+            check_plan(plan, &ctx.accounts.main_state.key())?;
+        }
 
-
-        
         let subscription = &mut ctx.accounts.subscription;
-        let lamports_in_subs_acc = (subscription.to_account_info().lamports() as f32) as u64;
-        msg!("SOL in PDA: {:?}", lamports_in_subs_acc as f32 / LAMPORTS_PER_SOL as f32);
-        
         let unix_time = ctx.accounts.clock.unix_timestamp * 1000;
-        let nominator = (subscription.authority_writable.valid_till - unix_time) as f32;
-        let denominator = (subscription.authority_writable.valid_till - subscription.subscription_status_writable.after_verify_utc_timestamp) as f32;
-        let partial  =  if nominator / denominator < 0.0 { 0.0 } else { nominator / denominator };  
-        
-        let current_used_lamports = subscription.authority_writable.used_lamports;
-        let lamports_as_credits = subscription.subscription_status_writable.after_verify_credit_lamports;
-        let fees = (100.0 - ctx.accounts.main_state.unsubscribe_fee as f32) / 100.0;
-        
+        subscription.settle(unix_time, ctx.accounts.main_state.grace_period)?;
+        let current_used_lamports = subscription.ledger.locked;
+        let lamports_as_credits = subscription.ledger.credits;
+        // debits not consumed yet by the running period
+        let unused_lamports = math::unused_part(
+            current_used_lamports,
+            subscription.subscription_status_writable.after_verify_utc_timestamp,
+            subscription.authority_writable.valid_till,
+            unix_time,
+        )?;
+        // the running period is refunded with the fees in force when it started
+        let period_fee_schedule = subscription.fee_schedule;
+        let fee_schedule = ctx.accounts.main_state.fee_schedule;
+        // with withdrawal the running period is terminated early, without it the unused debits go back to credits for another plan
+        // after the sunset started the users leave without fees, same as when refunded by process_sunset
+        let sunset = ctx.accounts.main_state.sunset_at.is_some();
+        let unused_fee = if sunset {
+            0
+        } else if withdraw_content {
+            math::fee_bps(unused_lamports, period_fee_schedule.early_termination_bps, period_fee_schedule.min_early_termination_fee)?
+        } else {
+            math::fee_bps(unused_lamports, period_fee_schedule.downgrade_bps, period_fee_schedule.min_downgrade_fee)?
+        };
+        // consumed part of the running period is earned, the fee is owed to the treasury
+        let ledger = &mut subscription.ledger;
+        ledger.earned = ledger.earned.checked_add(current_used_lamports - unused_lamports).ok_or(SubscriptionError::AirthemticError)?;
+        ledger.fees_owed = ledger.fees_owed.checked_add(unused_fee).ok_or(SubscriptionError::AirthemticError)?;
+        ledger.locked = 0;
+        ledger.credits = lamports_as_credits.checked_add(unused_lamports - unused_fee).ok_or(SubscriptionError::AirthemticError)?;
+
+        let mut refunded = 0;
+        let mut withdrawal_fee = 0;
         if withdraw_content {
             if ctx.accounts.to_account.is_none() {
                 return Err(SubscriptionError::MissingObligatoryAccount.into());
             }
             let to_pubkey = ctx.accounts.to_account.as_ref().unwrap().to_account_info();
-            let refund_to_user = ( partial *  fees  * current_used_lamports as f32 + lamports_as_credits as f32) as u64;
-            **subscription.to_account_info().try_borrow_mut_lamports()? -= refund_to_user as u64;
-            **to_pubkey.try_borrow_mut_lamports()? += refund_to_user;
-            // then all remaining lamports trnsfer from account to main_state PDA
-            let lamports_in_subs_acc = (subscription.to_account_info().lamports() - Rent::get()?.minimum_balance(subscription.to_account_info().data_len())) as u64;
-            msg!("This is problematic line with given lamports for with: {:?}", lamports_in_subs_acc);
-            **subscription.to_account_info().try_borrow_mut_lamports()? -= lamports_in_subs_acc;
-            **ctx.accounts.main_state_owner.to_account_info().try_borrow_mut_lamports()? += lamports_in_subs_acc;
-        }
-        msg!("partial: {:?}, fees: {:?}, current_used: {:?}, credits: {:?}", partial, fees, current_used_lamports as f32 / LAMPORTS_PER_SOL as f32, lamports_as_credits as f32/ LAMPORTS_PER_SOL as f32);
+            let funds = Funds::resolve(&ctx.accounts.main_state, &ctx.accounts.vault, &ctx.accounts.token_program)?;
+            let main_state_key = ctx.accounts.main_state.key();
+            let signer_seeds: &[&[&[u8]]] = &[&[b"subscription", ctx.accounts.user.key.as_ref(), main_state_key.as_ref(), &[ctx.bumps.subscription]]];
+            // credits after the withdrawal fee go back to the user, the owed fees are settled with the treasury
+            // the credits are charged the lower of the current withdrawal fee and the one in force since the subscription or its running period started
+            if !sunset {
+                withdrawal_fee = math::fee_bps(subscription.ledger.credits, fee_schedule.withdrawal_bps, fee_schedule.min_withdrawal_fee)?
+                    .min(math::fee_bps(subscription.ledger.credits, period_fee_schedule.withdrawal_bps, period_fee_schedule.min_withdrawal_fee)?);
+            }
+            let refund_to_user = subscription.ledger.credits - withdrawal_fee;
+            subscription.ledger.fees_owed = subscription.ledger.fees_owed.checked_add(withdrawal_fee).ok_or(SubscriptionError::AirthemticError)?;
+            let fees_owed = subscription.ledger.fees_owed;
+            funds.pay_out(&subscription.to_account_info(), signer_seeds, &to_pubkey, ctx.accounts.to_token_account.as_ref(), refund_to_user)?;
+            msg!("Fees transferred to the treasury: {:?}", fees_owed);
+            funds.pay_out(&subscription.to_account_info(), signer_seeds, &ctx.accounts.treasury.to_account_info(), ctx.accounts.treasury_vault.as_ref(), fees_owed)?;
+            let treasury = &mut ctx.accounts.treasury;
+            treasury.collected = treasury.collected.checked_add(fees_owed).ok_or(SubscriptionError::AirthemticError)?;
+            subscription.ledger.credits = 0;
+            subscription.ledger.fees_owed = 0;
+            refunded = refund_to_user;
+        }
+        msg!("unused: {:?}, unused fee: {:?}, withdrawal fee: {:?}, current_used: {:?}, credits: {:?}", unused_lamports, unused_fee, withdrawal_fee, current_used_lamports, lamports_as_credits);
         subscription.authority_writable = AuthorityWritable {
-            current_account_type: SubscriptionType::FREE,
+            current_plan: Pubkey::default(),
             valid_till: 0,
-            used_lamports: 0,
         };
+        // the subscription without any period stays pending, the suspended one stays suspended
+        if subscription.status != SubscriptionStatus::Pending && subscription.status != SubscriptionStatus::Suspended {
+            subscription.transition(SubscriptionStatus::Expired)?;
+        }
         subscription.subscription_status_writable = CurrentSubscriptionStatistics {
-            after_verify_credit_lamports: if withdraw_content { 0 } else { (current_used_lamports as f32 * partial * (fees + (1.0 - fees) / 2.0  ) ) as u64 + lamports_as_credits },
             after_verify_utc_timestamp: unix_time,
-            desired_subscription_type: if change_desired_subs_type.is_some() { change_desired_subs_type.unwrap() } else { SubscriptionType::FREE },
+            desired_plan: new_desired_plan.unwrap_or_default(),
+            auto_renew: subscription.subscription_status_writable.auto_renew,
         };
+        emit_cpi!(Unsubscribed {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            withdraw_content,
+            refunded,
+            fee: unused_fee + withdrawal_fee,
+            credits: subscription.ledger.credits,
+        });
         Ok(())
     }
     /**
-     * Authority from main_state PDA is allowed to withdraw funds from the subscription account.
+     * Treasurer of the main_state PDA is allowed to withdraw funds from the subscription account to the treasury of the main state.
      * He can either enter the amount he wants to withdraw or withdraw all the funds from the account.
-     * In both cases he can withdraw only the funds that have been used for subscription.
-     * Meaning: TOTAL_PDA_LAMPORTS - RENT_EXEMPT_BALANCE - CREDITS_LAMPORTS - IF(VALID_TILL > NOW) {USED_LAMPORTS} ELSE {0}
+     * In both cases he can withdraw only the earned funds, i.e. debits of the finished periods and the consumed part of the terminated ones.
      */
     pub fn withdraw(ctx: Context<WithdrawFromSubcription>, withdrawal_amount: Option<u64>) -> Result<()> {
         msg!("Params: {:?}", withdrawal_amount);
+        ctx.accounts.main_state.check_not_paused(PAUSE_WITHDRAW)?;
         if ctx.accounts.main_state.key() != ctx.accounts.subscription.imutable_initialized.main_state_pda.key() {
             return Err(SubscriptionError::IncorrectMainState.into());
         }
-        let authority = &mut ctx.accounts.main_state.authority;
-        if authority != ctx.accounts.authority.key {
+        if !ctx.accounts.main_state.has_role(Role::Treasurer, ctx.accounts.treasurer.key) {
             return Err(SubscriptionError::InvalidAuthority.into());
         }
+        ctx.accounts.main_state.check_multisig(ctx.accounts.treasurer.key, ctx.remaining_accounts)?;
         let subscription = &mut ctx.accounts.subscription;
         let from_pubkey =   subscription.to_account_info();
-        let rent_exempt_balance = Rent::get()?.minimum_balance(from_pubkey.data_len());
-        let to_pubkey = ctx.accounts.to_account.to_account_info();
-        let account_balance = from_pubkey.lamports();
-        let max_allowed_to_withdraw = account_balance 
-            - rent_exempt_balance 
-            - subscription.subscription_status_writable.after_verify_credit_lamports 
-            -  if subscription.authority_writable.valid_till > ctx.accounts.clock.unix_timestamp * 1000 { subscription.authority_writable.used_lamports } else { 0 };
-        msg!("Max allowed to withdraw: {:?}", max_allowed_to_withdraw as f32 / LAMPORTS_PER_SOL as f32);
-        
-        if withdrawal_amount.is_none() {
-            // we are about to withdraw all the funds from the subscription account we can
-            **from_pubkey.try_borrow_mut_lamports()? -= max_allowed_to_withdraw;
-            **to_pubkey.try_borrow_mut_lamports()? += max_allowed_to_withdraw;
-            return Ok(());
-        }
+        let to_pubkey = ctx.accounts.treasury.to_account_info();
+        let funds = Funds::resolve(&ctx.accounts.main_state, &ctx.accounts.vault, &ctx.accounts.token_program)?;
+        subscription.settle(ctx.accounts.clock.unix_timestamp * 1000, ctx.accounts.main_state.grace_period)?;
+        let max_allowed_to_withdraw = subscription.ledger.earned;
 
-        if withdrawal_amount.unwrap() > max_allowed_to_withdraw {
+        // we are about to withdraw all the funds from the subscription account we can, if no amount is given
+        let amount = withdrawal_amount.unwrap_or(max_allowed_to_withdraw);
+        if amount > max_allowed_to_withdraw {
             return Err(SubscriptionError::WithdrawFromSubscription.into());
-        }        
-        **from_pubkey.try_borrow_mut_lamports()? -= withdrawal_amount.unwrap_or(0);
-        **to_pubkey.try_borrow_mut_lamports()? += withdrawal_amount.unwrap_or(0);
+        }
+        subscription.ledger.earned -= amount;
+        subscription.ledger.withdrawn = subscription.ledger.withdrawn.checked_add(amount).ok_or(SubscriptionError::AirthemticError)?;
+
+        let main_state_key = ctx.accounts.main_state.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"subscription", ctx.accounts.user.key.as_ref(), main_state_key.as_ref(), &[ctx.bumps.subscription]]];
+        funds.pay_out(&from_pubkey, signer_seeds, &to_pubkey, ctx.accounts.treasury_vault.as_ref(), amount)?;
+        let referral_share = collect_earned(&ctx.accounts.main_state, subscription, &mut ctx.accounts.treasury, ctx.accounts.referrer_balance.as_mut(), amount)?;
+        emit_cpi!(Withdrawn {
+            subscription: subscription.key(),
+            main_state: main_state_key,
+            to: to_pubkey.key(),
+            amount,
+            referral_share,
+        });
         Ok(())
     }
-}
\ No newline at end of file
+    /**
+     * Safe exit for the user while unsubscribe is paused, pays back only the credits, leaving the running period and the earned funds untouched.
+     */
+    pub fn emergency_withdraw_credits(ctx: Context<EmergencyWithdrawCredits>) -> Result<()> {
+        if ctx.accounts.main_state.paused & PAUSE_UNSUBSCRIBE == 0 {
+            return Err(MainStateError::UnsubscribeNotPaused.into());
+        }
+        if ctx.accounts.subscription.imutable_initialized.user != *ctx.accounts.user.key {
+            return Err(SubscriptionError::InvalidAuthority.into());
+        }
+        let subscription = &mut ctx.accounts.subscription;
+        let funds = Funds::resolve(&ctx.accounts.main_state, &ctx.accounts.vault, &ctx.accounts.token_program)?;
+        let amount = subscription.ledger.credits;
+        subscription.ledger.credits = 0;
+        let main_state_key = ctx.accounts.main_state.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"subscription", ctx.accounts.user.key.as_ref(), main_state_key.as_ref(), &[ctx.bumps.subscription]]];
+        funds.pay_out(&subscription.to_account_info(), signer_seeds, &ctx.accounts.user.to_account_info(), ctx.accounts.user_token_account.as_ref(), amount)?;
+        emit_cpi!(EmergencyCreditsWithdrawn {
+            subscription: subscription.key(),
+            main_state: main_state_key,
+            amount,
+        });
+        Ok(())
+    }
+
+    /**
+     * Closes the subscription without an active period, credits and locked debits, returning its rent (and the rent of its vault) to the user.
+     * Earned funds and owed fees still held by the subscription are moved to the treasury first, together with the tokens sent to the vault directly.
+     * The user can create a new subscription later.
+     */
+    pub fn close_subscription(ctx: Context<CloseSubscription>) -> Result<()> {
+        ctx.accounts.main_state.check_not_paused(PAUSE_UNSUBSCRIBE)?;
+        let now = ctx.accounts.clock.unix_timestamp * 1000;
+        let subscription = &mut ctx.accounts.subscription;
+        if subscription.imutable_initialized.user != *ctx.accounts.user.key {
+            return Err(SubscriptionError::InvalidAuthority.into());
+        }
+        subscription.settle(now, ctx.accounts.main_state.grace_period)?;
+        if subscription.authority_writable.valid_till > now {
+            return Err(SubscriptionError::ActiveSubscription.into());
+        }
+        // closing would drop the suspension
+        if subscription.status == SubscriptionStatus::Suspended {
+            return Err(SubscriptionError::SubscriptionSuspended.into());
+        }
+        if subscription.ledger.credits > 0 || subscription.ledger.locked > 0 {
+            return Err(SubscriptionError::SubscriptionNotEmpty.into());
+        }
+        let funds = Funds::resolve(&ctx.accounts.main_state, &ctx.accounts.vault, &ctx.accounts.token_program)?;
+        let owed = subscription.ledger.earned.checked_add(subscription.ledger.fees_owed).ok_or(SubscriptionError::AirthemticError)?;
+        // tokens sent to the vault directly would block closing it, the treasury collects them
+        let dust = funds.excess(owed)?;
+        let paid_to_treasury = owed.checked_add(dust).ok_or(SubscriptionError::AirthemticError)?;
+        let main_state_key = ctx.accounts.main_state.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"subscription", ctx.accounts.user.key.as_ref(), main_state_key.as_ref(), &[ctx.bumps.subscription]]];
+        funds.pay_out(&subscription.to_account_info(), signer_seeds, &ctx.accounts.treasury.to_account_info(), ctx.accounts.treasury_vault.as_ref(), paid_to_treasury)?;
+        collect_earned(&ctx.accounts.main_state, subscription, &mut ctx.accounts.treasury, ctx.accounts.referrer_balance.as_mut(), subscription.ledger.earned)?;
+        let treasury = &mut ctx.accounts.treasury;
+        let fees_and_dust = subscription.ledger.fees_owed.checked_add(dust).ok_or(SubscriptionError::AirthemticError)?;
+        treasury.collected = treasury.collected.checked_add(fees_and_dust).ok_or(SubscriptionError::AirthemticError)?;
+        funds.close_vault(&subscription.to_account_info(), signer_seeds, &ctx.accounts.user.to_account_info())?;
+        let main_state = &mut ctx.accounts.main_state;
+        main_state.open_subscriptions = main_state.open_subscriptions.checked_sub(1).ok_or(SubscriptionError::AirthemticError)?;
+        emit_cpi!(SubscriptionClosed {
+            subscription: subscription.key(),
+            main_state: main_state_key,
+            user: *ctx.accounts.user.key,
+            paid_to_treasury,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+use crate::state::main_state::{MainState, PAUSE_SUNSET};
+use crate::state::treasury::Treasury;
+
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct StartSunset<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+// subscriptions are passed in the remaining accounts as [subscription, user, referrer_balance] for lamports (including the legacy subscriptions),
+// [subscription, user, referrer_balance, vault, user_token_account] for the main state with accepted mint.
+// The referrer balance is the program id for the subscriptions without a referrer
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ProcessSunset<'info> {
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    #[account(mut, seeds = [b"treasury", main_state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    pub keeper: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Treasury vault PDA, validated against the accepted mint in the instruction
+    #[account(mut, seeds = [b"treasury_vault", main_state.key().as_ref()], bump)]
+    pub treasury_vault: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CloseMainState<'info> {
+    #[account(mut, close = signer)]
+    pub main_state: Account<'info, MainState>,
+    #[account(mut, close = signer, seeds = [b"treasury", main_state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Treasury vault PDA, unpacked in the instruction
+    #[account(mut, seeds = [b"treasury_vault", main_state.key().as_ref()], bump)]
+    pub treasury_vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: Unpacked and validated against the accepted mint in the instruction, obligatory only when the treasury vault holds tokens
+    #[account(mut)]
+    pub signer_token_account: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+pub mod processor {
+    use anchor_lang::Discriminator;
+    use crate::errors::error::{MainStateError, SubscriptionError};
+    use crate::events::event::*;
+    use crate::instructions::referral::processor::collect_earned;
+    use crate::state::legacy::{read_legacy, LegacySubscription, LEGACY_SUBSCRIPTION_SPACE};
+    use crate::state::referral::ReferrerBalance;
+    use crate::state::subscription::Subscription;
+    use crate::utils::funds::{close_program_account, Funds};
+    use crate::utils::math;
+
+    use super::*;
+
+    /**
+     * Starts winding down the main state, no new subscriptions, deposits or periods from now on. It can't be undone.
+     */
+    pub fn start_sunset(ctx: Context<StartSunset>) -> Result<()> {
+        let main_state = &mut ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        main_state.check_not_sunset()?;
+        let sunset_at = ctx.accounts.clock.unix_timestamp * 1000;
+        main_state.sunset_at = Some(sunset_at);
+        emit_cpi!(SunsetStarted {
+            main_state: main_state.key(),
+            sunset_at,
+        });
+        Ok(())
+    }
+
+    /**
+     * Refunds and closes the legacy subscription (version 0) of the migrated main state, paid in lamports.
+     * The balance above the refund and the rent is the consumed part and the earned funds, same as the migration would compute. Returns the refund and the amount paid to the treasury.
+     */
+    fn refund_legacy<'info>(subscription_info: &AccountInfo<'info>, user: &AccountInfo<'info>, treasury: &AccountInfo<'info>, main_state_key: &Pubkey, program_id: &Pubkey, now: i64) -> Result<(u64, u64)> {
+        if subscription_info.owner != program_id {
+            return Err(ErrorCode::AccountOwnedByWrongProgram.into());
+        }
+        let legacy: LegacySubscription = read_legacy(&subscription_info.try_borrow_data()?, Subscription::DISCRIMINATOR, LEGACY_SUBSCRIPTION_SPACE)?;
+        if legacy.imutable_initialized.main_state_pda != *main_state_key {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if legacy.imutable_initialized.user != user.key() {
+            return Err(SubscriptionError::InvalidAuthority.into());
+        }
+        let period = &legacy.authority_writable;
+        let unused = math::unused_part(period.used_lamports, legacy.subscription_status_writable.after_verify_utc_timestamp, period.valid_till, now)?;
+        let refund = legacy.subscription_status_writable.after_verify_credit_lamports.checked_add(unused).ok_or(SubscriptionError::AirthemticError)?;
+        let paid_to_treasury = subscription_info.lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(LEGACY_SUBSCRIPTION_SPACE))
+            .saturating_sub(refund);
+        **subscription_info.try_borrow_mut_lamports()? -= paid_to_treasury;
+        **treasury.try_borrow_mut_lamports()? += paid_to_treasury;
+        // the refund goes with the rent
+        close_program_account(subscription_info, user)?;
+        Ok((refund, paid_to_treasury))
+    }
+
+    /**
+     * Refunds and closes the given batch of subscriptions of the sunset main state. Each user gets the credits and the unused part
+     * of the running period back without fees, together with the rent. The consumed part, the earned funds, the owed fees and the tokens
+     * sent to the vault directly go to the treasury, the referral share of the earned funds accrues to the referrer.
+     */
+    pub fn process_sunset<'info>(ctx: Context<'_, '_, 'info, 'info, ProcessSunset<'info>>) -> Result<()> {
+        ctx.accounts.main_state.check_not_paused(PAUSE_SUNSET)?;
+        if ctx.accounts.main_state.sunset_at.is_none() {
+            return Err(MainStateError::SunsetNotStarted.into());
+        }
+        let now = ctx.accounts.clock.unix_timestamp * 1000;
+        let main_state_key = ctx.accounts.main_state.key();
+        let chunk_len = if ctx.accounts.main_state.accepted_mint.is_some() { 5 } else { 3 };
+        let batch = ctx.remaining_accounts.chunks_exact(chunk_len);
+        if ctx.remaining_accounts.is_empty() || !batch.remainder().is_empty() {
+            return Err(SubscriptionError::MissingObligatoryAccount.into());
+        }
+        for accounts in batch {
+            let (subscription_info, user) = (&accounts[0], &accounts[1]);
+            let (refund, paid_to_treasury) = if subscription_info.data_len() == LEGACY_SUBSCRIPTION_SPACE {
+                // legacy subscriptions not migrated yet are not counted as open
+                let treasury = &mut ctx.accounts.treasury;
+                let refunded = refund_legacy(subscription_info, user, &treasury.to_account_info(), &main_state_key, ctx.program_id, now)?;
+                treasury.collected = treasury.collected.checked_add(refunded.1).ok_or(SubscriptionError::AirthemticError)?;
+                refunded
+            } else {
+                let subscription: Account<Subscription> = Account::try_from(subscription_info)?;
+                if subscription.imutable_initialized.main_state_pda != main_state_key {
+                    return Err(SubscriptionError::IncorrectMainState.into());
+                }
+                if subscription.imutable_initialized.user != user.key() {
+                    return Err(SubscriptionError::InvalidAuthority.into());
+                }
+                let (_, bump) = Pubkey::find_program_address(&[b"subscription", user.key.as_ref(), main_state_key.as_ref()], ctx.program_id);
+                let signer_seeds: &[&[&[u8]]] = &[&[b"subscription", user.key.as_ref(), main_state_key.as_ref(), &[bump]]];
+                let mut referrer_balance = match &accounts[2] {
+                    placeholder if placeholder.key == ctx.program_id => None,
+                    referrer_balance => Some(Account::<ReferrerBalance>::try_from(referrer_balance)?),
+                };
+                let (vault, user_token) = match accounts.get(3..5) {
+                    Some([vault, user_token]) => {
+                        // the remaining accounts aren't checked by the seeds constraint, any other token account of the subscription would leave the vault open
+                        let (vault_address, _) = Pubkey::find_program_address(&[b"vault", subscription_info.key.as_ref()], ctx.program_id);
+                        if vault.key() != vault_address {
+                            return Err(SubscriptionError::InvalidTokenAccount.into());
+                        }
+                        (Some(vault), Some(UncheckedAccount::try_from(user_token)))
+                    },
+                    _ => (None, None),
+                };
+                let funds = Funds::resolve_vault(&ctx.accounts.main_state, vault, &ctx.accounts.token_program)?;
+
+                // the running period is terminated without fees, its consumed part is earned
+                let ledger = &subscription.ledger;
+                let unused = math::unused_part(ledger.locked, subscription.subscription_status_writable.after_verify_utc_timestamp, subscription.authority_writable.valid_till, now)?;
+                let earned = ledger.earned.checked_add(ledger.locked - unused).ok_or(SubscriptionError::AirthemticError)?;
+                let refund = ledger.credits.checked_add(unused).ok_or(SubscriptionError::AirthemticError)?;
+                let owed = earned.checked_add(ledger.fees_owed).ok_or(SubscriptionError::AirthemticError)?;
+                // tokens sent to the vault directly would block closing it, the treasury collects them
+                let dust = funds.excess(refund.checked_add(owed).ok_or(SubscriptionError::AirthemticError)?)?;
+                let paid_to_treasury = owed.checked_add(dust).ok_or(SubscriptionError::AirthemticError)?;
+
+                funds.pay_out(subscription_info, signer_seeds, user, user_token.as_ref(), refund)?;
+                funds.pay_out(subscription_info, signer_seeds, &ctx.accounts.treasury.to_account_info(), ctx.accounts.treasury_vault.as_ref(), paid_to_treasury)?;
+                collect_earned(&ctx.accounts.main_state, &subscription, &mut ctx.accounts.treasury, referrer_balance.as_mut(), earned)?;
+                if let Some(referrer_balance) = &referrer_balance {
+                    referrer_balance.exit(ctx.program_id)?;
+                }
+                let treasury = &mut ctx.accounts.treasury;
+                let fees_and_dust = ledger.fees_owed.checked_add(dust).ok_or(SubscriptionError::AirthemticError)?;
+                treasury.collected = treasury.collected.checked_add(fees_and_dust).ok_or(SubscriptionError::AirthemticError)?;
+                funds.close_vault(subscription_info, signer_seeds, user)?;
+                close_program_account(subscription_info, user)?;
+                let main_state = &mut ctx.accounts.main_state;
+                main_state.open_subscriptions = main_state.open_subscriptions.checked_sub(1).ok_or(SubscriptionError::AirthemticError)?;
+                (refund, paid_to_treasury)
+            };
+            emit_cpi!(SunsetRefunded {
+                subscription: subscription_info.key(),
+                main_state: main_state_key,
+                user: user.key(),
+                refund,
+                paid_to_treasury,
+            });
+        }
+        Ok(())
+    }
+
+    /**
+     * Closes the sunset main state without open subscriptions together with its swept treasury without unclaimed referral rewards, returning the rent to the owner.
+     * Tokens sent to the treasury vault directly are not tracked by the treasury, they go to the token account of the owner before the vault is closed.
+     */
+    pub fn close_main_state(ctx: Context<CloseMainState>) -> Result<()> {
+        let main_state = &ctx.accounts.main_state;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        if main_state.sunset_at.is_none() {
+            return Err(MainStateError::SunsetNotStarted.into());
+        }
+        if main_state.open_subscriptions > 0 {
+            return Err(MainStateError::OpenSubscriptions.into());
+        }
+        let treasury = &ctx.accounts.treasury;
+        if treasury.collected > treasury.swept {
+            return Err(MainStateError::TreasuryNotSwept.into());
+        }
+        if treasury.referral_owed > 0 {
+            return Err(MainStateError::ReferralRewardsUnclaimed.into());
+        }
+        let funds = Funds::resolve(main_state, &ctx.accounts.treasury_vault, &ctx.accounts.token_program)?;
+        let main_state_key = main_state.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", main_state_key.as_ref(), &[treasury.bump]]];
+        let dust = funds.excess(0)?;
+        funds.pay_out(&treasury.to_account_info(), signer_seeds, &ctx.accounts.signer.to_account_info(), ctx.accounts.signer_token_account.as_ref(), dust)?;
+        funds.close_vault(&treasury.to_account_info(), signer_seeds, &ctx.accounts.signer.to_account_info())?;
+        emit_cpi!(MainStateClosed {
+            main_state: main_state_key,
+            owner: main_state.owner,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+use crate::state::main_state::{MainState, PAUSE_TREASURY};
+use crate::state::treasury::Treasury;
+
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SweepTreasury<'info> {
+    #[account(mut, seeds = [b"treasury", main_state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    pub main_state: Account<'info, MainState>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+    /// CHECK: Validated to be the payout address of the main state
+    #[account(mut)]
+    pub payout: AccountInfo<'info>,
+    // token accounts, obligatory only for the main state with accepted mint
+    /// CHECK: Treasury vault PDA, unpacked in the instruction
+    #[account(mut, seeds = [b"treasury_vault", main_state.key().as_ref()], bump)]
+    pub treasury_vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: Unpacked and validated against the accepted mint in the instruction
+    #[account(mut)]
+    pub payout_token_account: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+pub mod processor {
+    use crate::errors::error::SubscriptionError;
+    use crate::events::event::*;
+    use crate::utils::funds::Funds;
+
+    use super::*;
+    /**
+     * Moves the collected and not yet swept funds of the treasury to the payout address of the main state.
+     * Lamports sent to the treasury directly are never swept, same as with the subscription accounts.
+     */
+    pub fn sweep_treasury(ctx: Context<SweepTreasury>, amount: Option<u64>) -> Result<()> {
+        msg!("Params: {:?}", amount);
+        let main_state = &ctx.accounts.main_state;
+        main_state.check_not_paused(PAUSE_TREASURY)?;
+        if main_state.owner != *ctx.accounts.signer.key {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        main_state.check_multisig(ctx.accounts.signer.key, ctx.remaining_accounts)?;
+        if main_state.payout_address != ctx.accounts.payout.key() {
+            return Err(ErrorCode::ConstraintAddress.into());
+        }
+        let treasury = &mut ctx.accounts.treasury;
+        let available = treasury.collected.checked_sub(treasury.swept).ok_or(SubscriptionError::AirthemticError)?;
+        let amount = amount.unwrap_or(available);
+        if amount > available {
+            return Err(SubscriptionError::WithdrawFromSubscription.into());
+        }
+        treasury.swept = treasury.swept.checked_add(amount).ok_or(SubscriptionError::AirthemticError)?;
+        let funds = Funds::resolve(main_state, &ctx.accounts.treasury_vault, &ctx.accounts.token_program)?;
+        let main_state_key = main_state.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", main_state_key.as_ref(), &[treasury.bump]]];
+        funds.pay_out(&treasury.to_account_info(), signer_seeds, &ctx.accounts.payout, ctx.accounts.payout_token_account.as_ref(), amount)?;
+        emit_cpi!(TreasurySwept {
+            main_state: main_state_key,
+            payout: ctx.accounts.payout.key(),
+            amount,
+        });
+        Ok(())
+    }
+}
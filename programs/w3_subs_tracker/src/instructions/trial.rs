@@ -0,0 +1,109 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+use crate::state::main_state::MainState;
+use crate::state::plan::Plan;
+use crate::state::subscription::Subscription;
+use crate::state::trial::TrialRecord;
+
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct StartTrial<'info> {
+    // created without a deposit when the user has no subscription yet
+    #[account(init_if_needed, payer = user, space = 8 + Subscription::INIT_SPACE, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    // fails to initialize when the wallet already used the trial of the main state
+    #[account(init, payer = user, space = 8 + TrialRecord::INIT_SPACE, seeds = [b"trial", main_state.key().as_ref(), user.key().as_ref()], bump)]
+    pub trial_record: Account<'info, TrialRecord>,
+    #[account(mut)]
+    pub main_state: Account<'info, MainState>,
+    pub plan: Account<'info, Plan>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub clock: Sysvar<'info, Clock>,
+    // token accounts, obligatory only for the main state with accepted mint when the subscription is created
+    /// CHECK: Validated to be the accepted mint in the instruction
+    pub mint: Option<UncheckedAccount<'info>>,
+    /// CHECK: Vault token account owned by the subscription PDA, created in the instruction
+    #[account(mut, seeds = [b"vault", subscription.key().as_ref()], bump)]
+    pub vault: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+pub mod processor {
+    use crate::errors::error::{PlanError, SubscriptionError};
+    use crate::events::event::*;
+    use crate::instructions::subscription::processor::{check_plan, initialize};
+    use crate::state::main_state::PAUSE_ACTIVATE;
+    use crate::state::subscription::{AuthorityWritable, SubscriptionStatus, Usage};
+    use crate::utils::funds::create_token_account;
+
+    use super::*;
+
+    /**
+     * Starts the free trial of the plan without any payment: the plan becomes the current plan for the trial period.
+     * The trial is granted once per wallet and main state, even if the subscription is closed and created again.
+     */
+    pub fn start_trial(ctx: Context<StartTrial>) -> Result<()> {
+        msg!("Params: {:?}", ctx.accounts.plan.key());
+        ctx.accounts.main_state.check_not_paused(PAUSE_ACTIVATE)?;
+        ctx.accounts.main_state.check_not_sunset()?;
+        let plan = &ctx.accounts.plan;
+        check_plan(plan, &ctx.accounts.main_state.key())?;
+        if plan.trial_period == 0 {
+            return Err(PlanError::NoTrial.into());
+        }
+        let now = ctx.accounts.clock.unix_timestamp * 1000;
+        let subscription = &mut ctx.accounts.subscription;
+        // zeroed account means the subscription was just created
+        if subscription.version == 0 {
+            initialize(subscription, &mut ctx.accounts.main_state, ctx.accounts.user.key, None, plan.key(), now, 0)?;
+            if let Some(accepted_mint) = ctx.accounts.main_state.accepted_mint {
+                match (&ctx.accounts.mint, &ctx.accounts.vault, &ctx.accounts.token_program) {
+                    (Some(mint), Some(vault), Some(token_program)) if mint.key() == accepted_mint => {
+                        let subscription_key = subscription.key();
+                        let vault_seeds: &[&[u8]] = &[b"vault", subscription_key.as_ref(), &[ctx.bumps.vault.unwrap()]];
+                        create_token_account(&ctx.accounts.user, vault, vault_seeds, &mint.to_account_info(), &subscription.to_account_info(), &ctx.accounts.system_program, token_program)?;
+                    },
+                    _ => return Err(SubscriptionError::MissingObligatoryAccount.into()),
+                }
+            }
+        }
+        if subscription.trial_used {
+            return Err(SubscriptionError::TrialAlreadyUsed.into());
+        }
+        subscription.settle(now, ctx.accounts.main_state.grace_period)?;
+        if subscription.authority_writable.valid_till > now {
+            return Err(SubscriptionError::ActiveSubscription.into());
+        }
+        subscription.transition(SubscriptionStatus::Active)?;
+        let valid_till = now.checked_add(plan.trial_period).ok_or(SubscriptionError::AirthemticError)?;
+        subscription.trial_used = true;
+        subscription.usage = Usage {
+            period_start: now,
+            ..Usage::default()
+        };
+        subscription.subscription_status_writable.desired_plan = plan.key();
+        subscription.subscription_status_writable.after_verify_utc_timestamp = now;
+        subscription.authority_writable = AuthorityWritable {
+            current_plan: plan.key(),
+            valid_till,
+        };
+        let trial_record = &mut ctx.accounts.trial_record;
+        trial_record.main_state = ctx.accounts.main_state.key();
+        trial_record.user = *ctx.accounts.user.key;
+        trial_record.plan = plan.key();
+        trial_record.started_at = now;
+        trial_record.bump = ctx.bumps.trial_record;
+        emit_cpi!(TrialStarted {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            user: *ctx.accounts.user.key,
+            plan: plan.key(),
+            valid_till,
+        });
+        Ok(())
+    }
+}
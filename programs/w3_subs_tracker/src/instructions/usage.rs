@@ -0,0 +1,80 @@
+use anchor_lang::prelude::*;
+use crate::state::main_state::{MainState, Role};
+use crate::state::plan::Plan;
+use crate::state::subscription::Subscription;
+
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RecordUsage<'info> {
+    #[account(mut, seeds = [b"subscription", user.key().as_ref(), main_state.key().as_ref()], bump)]
+    pub subscription: Account<'info, Subscription>,
+    pub main_state: Account<'info, MainState>,
+    // current plan of the subscription
+    pub plan: Account<'info, Plan>,
+    /// CHECK: This is not dangerous because we only use given account to access correct PDA
+    pub user: AccountInfo<'info>,
+    pub authority: Signer<'info>,
+    pub clock: Sysvar<'info, Clock>,
+}
+
+pub mod processor {
+    use crate::errors::error::{PlanError, SubscriptionError};
+    use crate::events::event::*;
+    use crate::state::main_state::PAUSE_SET_INFO;
+    use crate::state::subscription::SubscriptionStatus;
+
+    use super::*;
+
+    /**
+     * Charges the given units of the metered usage from the credits at the unit price of the current plan. The charged credits are earned right away.
+     * Usage is recorded while the subscription has the access, up to the usage cap of the plan per period.
+     */
+    pub fn record_usage(ctx: Context<RecordUsage>, units: u64) -> Result<()> {
+        msg!("Params: {:?}", units);
+        ctx.accounts.main_state.check_not_paused(PAUSE_SET_INFO)?;
+        ctx.accounts.main_state.check_not_sunset()?;
+        let subscription = &mut ctx.accounts.subscription;
+        if subscription.imutable_initialized.main_state_pda.key() != ctx.accounts.main_state.key() {
+            return Err(SubscriptionError::IncorrectMainState.into());
+        }
+        if !ctx.accounts.main_state.has_role(Role::Verifier, ctx.accounts.authority.key) {
+            return Err(SubscriptionError::InvalidAuthority.into());
+        }
+        let plan = &ctx.accounts.plan;
+        if subscription.authority_writable.current_plan != plan.key() {
+            return Err(PlanError::NotCurrentPlan.into());
+        }
+        if plan.unit_price == 0 {
+            return Err(PlanError::NotMetered.into());
+        }
+        subscription.settle(ctx.accounts.clock.unix_timestamp * 1000, ctx.accounts.main_state.grace_period)?;
+        match subscription.status {
+            SubscriptionStatus::Active | SubscriptionStatus::CancelAtPeriodEnd | SubscriptionStatus::Grace => {},
+            SubscriptionStatus::Suspended => return Err(SubscriptionError::SubscriptionSuspended.into()),
+            _ => return Err(SubscriptionError::NoActiveSubscription.into()),
+        }
+        let period_units = subscription.usage.units.checked_add(units).ok_or(SubscriptionError::AirthemticError)?;
+        if plan.usage_cap.is_some_and(|usage_cap| period_units > usage_cap) {
+            return Err(SubscriptionError::UsageCapExceeded.into());
+        }
+        let amount = units.checked_mul(plan.unit_price).ok_or(SubscriptionError::AirthemticError)?;
+        if subscription.ledger.credits < amount {
+            return Err(SubscriptionError::NotEnoughCredits.into());
+        }
+        subscription.ledger.credits -= amount;
+        subscription.ledger.earned = subscription.ledger.earned.checked_add(amount).ok_or(SubscriptionError::AirthemticError)?;
+        subscription.usage.units = period_units;
+        subscription.usage.charged = subscription.usage.charged.checked_add(amount).ok_or(SubscriptionError::AirthemticError)?;
+        emit_cpi!(UsageRecorded {
+            subscription: subscription.key(),
+            main_state: ctx.accounts.main_state.key(),
+            plan: plan.key(),
+            units,
+            amount,
+            period_units,
+            credits: subscription.ledger.credits,
+        });
+        Ok(())
+    }
+}
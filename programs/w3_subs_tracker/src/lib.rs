@@ -2,9 +2,20 @@ use anchor_lang::prelude::*;
 mod instructions;
 mod state;
 mod errors;
+mod utils;
+mod events;
+use instructions::coupon::*;
 use instructions::main_state::*;
+use instructions::migration::*;
+use instructions::plan::*;
+use instructions::referral::*;
 use instructions::subscription::*;
-use state::subscription::*;
+use instructions::sunset::*;
+use instructions::treasury::*;
+use instructions::trial::*;
+use instructions::usage::*;
+use state::coupon::Coupon;
+use state::main_state::{FeeSchedule, Role};
 
 
 
@@ -15,69 +26,356 @@ declare_id!("31K77tro5CwWDDnKuxmzBNQZJgc1PsgERiUrpc914uZ8");
 
 #[program]
 mod w_3_subs_tracker {
-    use self::instructions::{main_state, subscription};
+    use self::instructions::{coupon, main_state, migration, plan, referral, subscription, sunset, treasury, trial, usage};
 
     use super::*;
     /**
-     * Initialize main state, which defines who is the owner and the authority. After this step owner can update the authority and the owner.
-     * Authority can interact with subscription instructions.
+     * Initialize main state, which defines who is the owner and the authority, and the fee schedule of the unsubscription. After this step owner can update the authority and the owner.
+     * Authority can interact with subscription instructions, the verifier, treasurer, fee manager and pauser roles are granted to the owner.
+     * Each tenant (product) gets its own main state derived from the initializing wallet and the given tenant_id, subscriptions are then keyed by the main state pubkey.
+     * Optional accepted_mint account makes the subscriptions of the main state paid in the given SPL token instead of native SOL.
+     * Creates the treasury PDA (and its token vault with accepted mint) collecting the fees and the earned revenue, swept to the payout address, which is the owner initially.
+     * Fee, bounty and role changes are applied config_delay milliseconds (at least MIN_CONFIG_DELAY) after they are queued.
      * Authorised: MainState.owner
      */
-    pub fn intialize_main_state(ctx: Context<InitializeMainState>, fees: u8) -> Result<()> {
-        main_state::processor::intialize_main_state(ctx, fees)
+    pub fn intialize_main_state(ctx: Context<InitializeMainState>, tenant_id: u64, fee_schedule: FeeSchedule, config_delay: i64) -> Result<()> {
+        main_state::processor::intialize_main_state(ctx, tenant_id, fee_schedule, config_delay)
     }
     /**
-     * Instruction which will be used to update the authority of the main state account
+     * Instruction which proposes the new authority of the main state account, stored as MainState.pending_authority until accepted.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn propose_authority(ctx: Context<UpdateAuthority>, new_authority: Pubkey) -> Result<()> {
+        main_state::processor::propose_authority(ctx, new_authority)
+    }
+    /**
+     * Instruction which makes the pending authority the authority of the main state account.
+     * Authorised: MainState.pending_authority
+     */
+    pub fn accept_authority(ctx: Context<UpdateAuthority>) -> Result<()> {
+        main_state::processor::accept_authority(ctx)
+    }
+    /**
+     * Instruction which cancels the pending authority proposal.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn cancel_authority_proposal(ctx: Context<UpdateAuthority>) -> Result<()> {
+        main_state::processor::cancel_authority_proposal(ctx)
+    }
+    /**
+     * Instruction which proposes the new owner of the main state account, stored as MainState.pending_owner until accepted.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn propose_owner(ctx: Context<UpdateOwner>, new_owner: Pubkey) -> Result<()> {
+        main_state::processor::propose_owner(ctx, new_owner)
+    }
+    /**
+     * Instruction which makes the pending owner the owner of the main state account.
+     * Authorised: MainState.pending_owner
+     */
+    pub fn accept_owner(ctx: Context<UpdateOwner>) -> Result<()> {
+        main_state::processor::accept_owner(ctx)
+    }
+    /**
+     * Instruction which cancels the pending owner proposal.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn cancel_owner_proposal(ctx: Context<UpdateOwner>) -> Result<()> {
+        main_state::processor::cancel_owner_proposal(ctx)
+    }
+    /**
+     * Instruction which queues the new fee schedule (basis points and flat minimums) for the unsubcription related actions, applied by apply_pending_config after the config delay.
+     * Authorised: MainState.roles.fee_manager && MainState.multisig
+     */
+    pub fn update_fees(ctx: Context<UpdateFees>, new_fee_schedule: FeeSchedule) -> Result<()> {
+        main_state::processor::update_fees(ctx, new_fee_schedule)
+    }
+    /**
+     * Instruction which queues the new bounty paid to the keeper renewing a subscription, applied by apply_pending_config after the config delay.
+     * The bounty paid by renew never exceeds MAX_RENEW_BOUNTY_BPS (5%) of the renewed plan price.
+     * Authorised: MainState.roles.fee_manager && MainState.multisig
+     */
+    pub fn update_renew_bounty(ctx: Context<UpdateRenewBounty>, new_bounty_lamports: u64) -> Result<()> {
+        main_state::processor::update_renew_bounty(ctx, new_bounty_lamports)
+    }
+    /**
+     * Instruction which sets the optional M-of-N signer set, which has to co-sign withdrawals, fee and bounty changes and owner/authority proposals.
+     * The other signers are passed as signing remaining accounts. Empty signers with zero threshold removes the multisig.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn set_multisig(ctx: Context<SetMultisig>, signers: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        main_state::processor::set_multisig(ctx, signers, threshold)
+    }
+    /**
+     * Instruction which queues granting the verifier, treasurer, fee manager or pauser role to the given key, replacing its previous holder once applied after the config delay.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn grant_role(ctx: Context<UpdateRole>, role: Role, grantee: Pubkey) -> Result<()> {
+        main_state::processor::grant_role(ctx, role, grantee)
+    }
+    /**
+     * Instruction which queues revoking the role, leaving it without a holder until granted again once applied after the config delay.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn revoke_role(ctx: Context<UpdateRole>, role: Role) -> Result<()> {
+        main_state::processor::revoke_role(ctx, role)
+    }
+    /**
+     * Instruction which queues the new minimum delay in milliseconds between queueing a fee or role change and applying it, at least MIN_CONFIG_DELAY (1 day).
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn set_config_delay(ctx: Context<UpdateConfig>, config_delay: i64) -> Result<()> {
+        main_state::processor::set_config_delay(ctx, config_delay)
+    }
+    /**
+     * Instruction which drops all the queued fee, bounty, role and delay changes.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn cancel_pending_config(ctx: Context<UpdateConfig>) -> Result<()> {
+        main_state::processor::cancel_pending_config(ctx)
+    }
+    /**
+     * Instruction which applies the queued fee, bounty, role and delay changes once the config delay they were queued with has passed, emitting FeesUpdated when the fee schedule changed.
+     * Authorised: *
+     */
+    pub fn apply_pending_config(ctx: Context<ApplyPendingConfig>) -> Result<()> {
+        main_state::processor::apply_pending_config(ctx)
+    }
+    /**
+     * Instruction which pauses the instruction groups given by the PAUSE_* bitmask, zero resumes all of them.
+     * Paused instructions fail with InstructionPaused, the main state configuration and verify_access are never paused.
+     * Authorised: MainState.owner || MainState.roles.pauser
+     */
+    pub fn set_pause(ctx: Context<SetPause>, paused: u16) -> Result<()> {
+        main_state::processor::set_pause(ctx, paused)
+    }
+    /**
+     * Instruction which sets the address the treasury is swept to.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn set_payout_address(ctx: Context<SetPayoutAddress>, payout_address: Pubkey) -> Result<()> {
+        main_state::processor::set_payout_address(ctx, payout_address)
+    }
+    /**
+     * Instruction which sets the key signing the discount coupons, None stops accepting any coupons.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn set_coupon_signer(ctx: Context<SetCouponSigner>, coupon_signer: Option<Pubkey>) -> Result<()> {
+        main_state::processor::set_coupon_signer(ctx, coupon_signer)
+    }
+    /**
+     * Instruction which sets the share (in bps) of the withdrawn debits accrued to the referrers of the subscriptions.
+     * Authorised: MainState.roles.fee_manager && MainState.multisig
+     */
+    pub fn set_referral_bps(ctx: Context<SetReferralBps>, referral_bps: u16) -> Result<()> {
+        referral::processor::set_referral_bps(ctx, referral_bps)
+    }
+    /**
+     * Instruction which pays the accrued referral rewards of the referrer out of the treasury.
+     * Authorised: ReferrerBalance.referrer
+     */
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        referral::processor::claim_referral_rewards(ctx)
+    }
+    /**
+     * Instruction which sets the grace period (in milliseconds) keeping the access after the end of the renewable period, 0 for none.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn set_grace_period(ctx: Context<SetGracePeriod>, grace_period: i64) -> Result<()> {
+        main_state::processor::set_grace_period(ctx, grace_period)
+    }
+    /**
+     * Instruction which moves the given amount (all collected funds when not given) from the treasury to the payout address of the main state.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn sweep_treasury(ctx: Context<SweepTreasury>, amount: Option<u64>) -> Result<()> {
+        treasury::processor::sweep_treasury(ctx, amount)
+    }
+    /**
+     * Instruction which starts the irreversible wind down of the main state: subscriptions can't be created, funded, activated or renewed anymore.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn start_sunset(ctx: Context<StartSunset>) -> Result<()> {
+        sunset::processor::start_sunset(ctx)
+    }
+    /**
+     * Instruction which refunds and closes the batch of subscriptions given in the remaining accounts after the sunset started.
+     * Users get the credits, the unused part of the running period (without fees) and the rent back, the rest goes to the treasury.
+     * Legacy subscriptions not migrated yet are refunded the same way.
+     * Authorised: anyone (keeper)
+     */
+    pub fn process_sunset<'info>(ctx: Context<'_, '_, 'info, 'info, ProcessSunset<'info>>) -> Result<()> {
+        sunset::processor::process_sunset(ctx)
+    }
+    /**
+     * Instruction which closes the sunset main state and its treasury once all subscriptions are closed and the treasury is swept.
+     * Authorised: MainState.owner && MainState.multisig
+     */
+    pub fn close_main_state(ctx: Context<CloseMainState>) -> Result<()> {
+        sunset::processor::close_main_state(ctx)
+    }
+    /**
+     * Instruction which upgrades the main state created before the versioned layout in place, reallocating it and creating its treasury.
+     * The unsubscribe fee in percent becomes the fee schedule, the verifier role is given to the authority, the other roles and the payout address to the owner. Signer tops up the rent.
+     * Legacy subscriptions of the main state are counted as open subscriptions once they are migrated.
+     * Authorised: legacy MainState.owner
+     */
+    pub fn migrate_main_state(ctx: Context<MigrateMainState>) -> Result<()> {
+        migration::processor::migrate_main_state(ctx)
+    }
+    /**
+     * Instruction which upgrades the subscription created before the versioned layout in place, reallocating it and moving its balance into the ledger.
+     * Optional current and desired plans replace the legacy subscription types. Authority tops up the rent. The main state must be migrated first.
+     * Authorised: MainState.authority
+     */
+    pub fn migrate_subscription(ctx: Context<MigrateSubscription>) -> Result<()> {
+        migration::processor::migrate_subscription(ctx)
+    }
+    /**
+     * Instruction which creates a new plan (name, price, period length and tier) in the plan catalog of the main state.
      * Authorised: MainState.owner
      */
-    pub fn update_authority(ctx: Context<UpdateAuthority>, new_authority: Pubkey) -> Result<()> {
-        main_state::processor::update_authority(ctx, new_authority)
+    pub fn create_plan(ctx: Context<CreatePlan>, plan_id: u64, name: String, price_lamports: u64, period: i64, tier: u8) -> Result<()> {
+        plan::processor::create_plan(ctx, plan_id, name, price_lamports, period, tier)
     }
     /**
-     * Instruction which will be used to update the owner of the main state account
+     * Instruction which will be used to update an active plan, unspecified arguments retain their previous values.
      * Authorised: MainState.owner
      */
-    pub fn update_owner(ctx: Context<UpdateOwner>, new_owner: Pubkey) -> Result<()> {
-        main_state::processor::update_owner(ctx, new_owner)
+    pub fn update_plan(ctx: Context<UpdatePlan>, name: Option<String>, price_lamports: Option<u64>, period: Option<i64>, tier: Option<u8>) -> Result<()> {
+        plan::processor::update_plan(ctx, name, price_lamports, period, tier)
     }
     /**
-     * Instruction which will be used to update the fees for the unsubcription related actions.
-     * Authorised: MainState.authority
+     * Instruction which retires the plan, so it can't be chosen for any new subscription period.
+     * Authorised: MainState.owner
      */
-    pub fn update_fees(ctx: Context<UpdateFees>, new_fees: u8) -> Result<()> {
-        main_state::processor::update_fees(ctx, new_fees)
+    pub fn retire_plan(ctx: Context<UpdatePlan>) -> Result<()> {
+        plan::processor::retire_plan(ctx)
+    }
+    /**
+     * Instruction which sets the free trial length of the plan in milliseconds, 0 for no trial.
+     * Authorised: MainState.owner
+     */
+    pub fn set_plan_trial(ctx: Context<UpdatePlan>, trial_period: i64) -> Result<()> {
+        plan::processor::set_plan_trial(ctx, trial_period)
+    }
+    /**
+     * Instruction which makes the plan metered with the given price per unit of usage (0 stops the metering) and the optional cap of units per period.
+     * Authorised: MainState.owner
+     */
+    pub fn set_plan_metering(ctx: Context<UpdatePlan>, unit_price: u64, usage_cap: Option<u64>) -> Result<()> {
+        plan::processor::set_plan_metering(ctx, unit_price, usage_cap)
     }
 
 
 
     
     /**
-     * Instruction which creates new subscription for the user.
+     * Instruction which creates new subscription for the user, with the given plan account as the desired plan.
+     * For the main state with accepted mint the initial deposit is moved from the user token account to the newly created subscription vault.
+     * The optional referrer is stored immutably with the subscription and gets the referral share of its withdrawn debits.
      * Authorised: Subscription.user
      */
-    pub fn create_subscription(ctx: Context<CreateSubscription>, initial_deposit: u64, account_type: SubscriptionType) -> Result<()> {
-        subscription::processor::create_subscription(ctx, initial_deposit, account_type)
+    pub fn create_subscription(ctx: Context<CreateSubscription>, initial_deposit: u64, referrer: Option<Pubkey>) -> Result<()> {
+        subscription::processor::create_subscription(ctx, initial_deposit, referrer)
     }
     /**
-     * Instruction which will be used to change the desired subscription type for the user.
+     * Instruction which will be used to change the desired plan for the user to the given plan account.
      * Authorised: Subscription.user
      */
-    pub fn change_desired_subscription_type(ctx: Context<ChangeSubscriptionType>, account_type: SubscriptionType) -> Result<()> {
-        subscription::processor::change_desired_subscription_type(ctx, account_type)
+    pub fn change_desired_subscription_type(ctx: Context<ChangeSubscriptionType>) -> Result<()> {
+        subscription::processor::change_desired_subscription_type(ctx)
     }
     /**
-     * Instruction which will be used to set the subscription date for the user.
-     * Authorised: MainState.authority
+     * Instruction which activates the desired plan without the BE, when there is no active period and enough credits for the plan price.
+     * The plan price is moved from credits to debits and the subscription is valid for the plan period.
+     * Authorised: Subscription.user
+     */
+    pub fn activate_subscription(ctx: Context<ActivateSubscription>) -> Result<()> {
+        subscription::processor::activate_subscription(ctx)
+    }
+    /**
+     * Instruction which switches the active subscription from the current plan to the given new plan, keeping it continuously active.
+     * The unused part of the running period is credited back (fee-free for an upgrade to a higher tier, with the downgrade fee otherwise) and the new plan price is charged from the credits.
+     * Authorised: Subscription.user
+     */
+    pub fn change_plan(ctx: Context<ChangePlan>) -> Result<()> {
+        subscription::processor::change_plan(ctx)
+    }
+    /**
+     * Instruction which activates the desired plan like activate_subscription, for the plan price discounted by the coupon.
+     * The coupon must be signed by MainState.coupon_signer in the ed25519 program instruction right before this one, its redemptions are counted per nonce up to its max uses.
+     * Authorised: Subscription.user
+     */
+    pub fn activate_with_coupon(ctx: Context<ActivateWithCoupon>, coupon: Coupon) -> Result<()> {
+        coupon::processor::activate_with_coupon(ctx, coupon)
+    }
+    /**
+     * Instruction which starts the free trial of the plan without a deposit, creating the subscription when the user has none.
+     * The trial is granted once per wallet and main state, recorded in the trial record PDA and on the subscription.
+     * Authorised: Subscription.user
      */
-    pub fn set_subscription_info(ctx: Context<SetSubscriptionDate>, new_date: Option<i64>, acumulated_sol: Option<u64>, subscription_type: Option<SubscriptionType>) -> Result<()> {
-        subscription::processor::set_subscription_info(ctx, new_date, acumulated_sol, subscription_type)
+    pub fn start_trial(ctx: Context<StartTrial>) -> Result<()> {
+        trial::processor::start_trial(ctx)
     }
     /**
-     * Instruction which will be used to unsubscribe the user from the subscription, meaning sending back the last deposited funds to the user, sending remaining lamports to the main_state PDA and closing the account.
+     * Instruction which enables or disables the automatic renewal of the current plan.
+     * Authorised: Subscription.user
+     */
+    pub fn set_auto_renew(ctx: Context<SetAutoRenew>, auto_renew: bool) -> Result<()> {
+        subscription::processor::set_auto_renew(ctx, auto_renew)
+    }
+    /**
+     * Instruction which cancels the active subscription at the end of its period (turning off the auto renewal) or resumes the cancelled one.
+     * Authorised: Subscription.user
+     */
+    pub fn set_cancel_at_period_end(ctx: Context<SetCancelAtPeriodEnd>, cancel: bool) -> Result<()> {
+        subscription::processor::set_cancel_at_period_end(ctx, cancel)
+    }
+    /**
+     * Instruction which suspends the subscription (no access, no new periods) or lifts the suspension.
+     * Authorised: MainState.roles.verifier
+     */
+    pub fn set_suspended(ctx: Context<SetSuspended>, suspended: bool) -> Result<()> {
+        subscription::processor::set_suspended(ctx, suspended)
+    }
+    /**
+     * Instruction which renews the expired current plan of an auto renewing subscription from its credits, paying the renew bounty to the keeper.
+     * Authorised: *
+     */
+    pub fn renew(ctx: Context<RenewSubscription>) -> Result<()> {
+        subscription::processor::renew(ctx)
+    }
+    /**
+     * Instruction which checks that the user has an active period (or its grace period) of the current plan with at least the given tier, returning the timestamp the access ends.
+     * Fails with NoActiveSubscription, SubscriptionSuspended or InsufficientTier otherwise. Meant to be called by other programs via CPI (`cpi` feature).
+     * Authorised: *
+     */
+    pub fn verify_access(ctx: Context<VerifyAccess>, min_tier: u8) -> Result<i64> {
+        subscription::processor::verify_access(ctx, min_tier)
+    }
+    /**
+     * Instruction which will be used to set the subscription date for the user, the optional plan account becomes the current plan.
+     * Authorised: MainState.roles.verifier
+     */
+    pub fn set_subscription_info(ctx: Context<SetSubscriptionDate>, new_date: Option<i64>, acumulated_sol: Option<u64>) -> Result<()> {
+        subscription::processor::set_subscription_info(ctx, new_date, acumulated_sol)
+    }
+    /**
+     * Instruction which charges the given units of usage of the metered current plan from the credits, counting them for the period.
+     * Fails with UsageCapExceeded above the usage cap of the plan per period.
+     * Authorised: MainState.roles.verifier
+     */
+    pub fn record_usage(ctx: Context<RecordUsage>, units: u64) -> Result<()> {
+        usage::processor::record_usage(ctx, units)
+    }
+    /**
+     * Instruction which will be used to unsubscribe the user from the subscription, meaning sending back the last deposited funds to the user and the unsubscribe fee to the treasury of the main state.
+     * The optional plan account becomes the new desired plan. No fees are charged after the sunset started.
      * Authorised: MainState.authority || Subscription.user
      */
-    pub fn unsubscribe(ctx: Context<Unsubscribe>, withdraw_content: bool, new_desired_subs_type: Option<SubscriptionType>) -> Result<()> {
-        subscription::processor::unsubscribe(ctx, withdraw_content, new_desired_subs_type)
+    pub fn unsubscribe(ctx: Context<Unsubscribe>, withdraw_content: bool) -> Result<()> {
+        subscription::processor::unsubscribe(ctx, withdraw_content)
     }
     /**
      * Instruction which will be used to fund the subscription account with new deposit.
@@ -87,8 +385,23 @@ mod w_3_subs_tracker {
         subscription::processor::fund_subscription(ctx, new_deposit)
     }
     /**
-     * Instruction which will be used to withdraw the funds from the subscription account, to predefined account.
-     * Authorised: MainState.authority
+     * Instruction which pays the credits back to the user while unsubscribe is paused, the running period stays untouched.
+     * Authorised: Subscription.user
+     */
+    pub fn emergency_withdraw_credits(ctx: Context<EmergencyWithdrawCredits>) -> Result<()> {
+        subscription::processor::emergency_withdraw_credits(ctx)
+    }
+    /**
+     * Instruction which closes the subscription account without an active period, credits and locked debits, returning the rent to the user.
+     * Earned funds and owed fees left in the subscription are moved to the treasury first.
+     * Authorised: Subscription.user
+     */
+    pub fn close_subscription(ctx: Context<CloseSubscription>) -> Result<()> {
+        subscription::processor::close_subscription(ctx)
+    }
+    /**
+     * Instruction which will be used to withdraw the earned funds from the subscription account to the treasury of the main state.
+     * Authorised: MainState.roles.treasurer && MainState.multisig
      */
     pub fn withdraw(ctx: Context<WithdrawFromSubcription>, amount: Option<u64>) -> Result<()> {
         subscription::processor::withdraw(ctx, amount)
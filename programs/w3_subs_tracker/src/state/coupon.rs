@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+
+/**
+ * Discount payload signed off-chain by the coupon signer of the main state, its borsh serialization is the signed message.
+ */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct Coupon {
+    pub main_state: Pubkey,
+    pub plan: Pubkey,
+    // discount in basis points of the plan price, applied before the amount off
+    pub percent_off_bps: u16,
+    // flat discount in the plan price units
+    pub amount_off: u64,
+    // timestamp in milliseconds after which the coupon can't be redeemed
+    pub expires_at: i64,
+    pub max_uses: u32,
+    // identifies the coupon, part of the redemption PDA seeds
+    pub nonce: u64,
+}
+
+/**
+ * Number of the redemptions of the coupon with the given nonce, prevents its replay over the max uses.
+ */
+#[account]
+#[derive(InitSpace)]
+pub struct CouponRedemption {
+    pub main_state: Pubkey,
+    pub nonce: u64,
+    pub uses: u32,
+    pub bump: u8,
+}
@@ -0,0 +1,68 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::error::MigrationError;
+
+// account space of the layouts written before the version field, used to recognise the accounts to migrate
+pub const LEGACY_MAIN_STATE_SPACE: usize = 8 + 64 + 1;
+pub const LEGACY_SUBSCRIPTION_SPACE: usize = 64 + 17 + 17 + 8;
+
+/**
+ * Main state layout (version 0) with a single unsubscribe fee in percent, at the tenant-less "mainState" seed.
+ */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LegacyMainState {
+    pub owner: Pubkey,
+    pub authority: Pubkey,
+    pub unsubscribe_fee: u8,
+}
+
+/**
+ * Subscription layout (version 0) with the hard-coded subscription types instead of the plans and the credits kept in the status.
+ */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LegacySubscription {
+    pub imutable_initialized: LegacyMutableInitialized,
+    pub subscription_status_writable: LegacyStatus,
+    pub authority_writable: LegacyAuthorityWritable,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LegacyMutableInitialized {
+    pub main_state_pda: Pubkey,
+    pub user: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub enum LegacySubscriptionType {
+    Free,
+    Basic,
+    Premium,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LegacyStatus {
+    pub after_verify_credit_lamports: u64,
+    pub after_verify_utc_timestamp: i64,
+    pub desired_subscription_type: LegacySubscriptionType,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LegacyAuthorityWritable {
+    pub current_account_type: LegacySubscriptionType,
+    pub valid_till: i64,
+    pub used_lamports: u64,
+}
+
+/**
+ * Deserializes the legacy account data after checking the discriminator, which didn't change with the layout.
+ * Accounts with the discriminator but other than the legacy space were created with the versioned layout.
+ */
+pub fn read_legacy<T: AnchorDeserialize>(data: &[u8], discriminator: [u8; 8], space: usize) -> Result<T> {
+    if data.len() < 8 || data[..8] != discriminator {
+        return Err(MigrationError::InvalidLegacyAccount.into());
+    }
+    if data.len() != space {
+        return Err(MigrationError::AlreadyMigrated.into());
+    }
+    T::deserialize(&mut &data[8..]).map_err(|_| MigrationError::InvalidLegacyAccount.into())
+}
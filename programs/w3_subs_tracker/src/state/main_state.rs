@@ -1,8 +1,287 @@
 use anchor_lang::prelude::*;
+use crate::errors::error::{MainStateError, SubscriptionError};
+use crate::utils::math::BPS_DENOMINATOR;
+
+// layout version of the main state, legacy main states without the version field are version 0
+pub const MAIN_STATE_VERSION: u8 = 1;
 
 #[account]
+#[derive(InitSpace)]
 pub struct MainState {
+    pub version: u8,
     pub owner: Pubkey,
     pub authority: Pubkey,
-    pub unsubscribe_fee: u8,
-}
\ No newline at end of file
+    // fees charged by the refund logic of unsubscribe
+    pub fee_schedule: FeeSchedule,
+    // id of the tenant (merchant/product) this main state belongs to, used as a part of the main state seeds
+    pub tenant_id: u64,
+    pub bump: u8,
+    // lamports paid from the subscription credits to the keeper who renews the subscription, capped by MAX_RENEW_BOUNTY_BPS of the plan price
+    pub renew_bounty_lamports: u64,
+    // SPL token mint the subscriptions are paid in, native SOL when none
+    pub accepted_mint: Option<Pubkey>,
+    // proposed owner, becomes the owner once he accepts it
+    pub pending_owner: Option<Pubkey>,
+    // proposed authority, becomes the authority once he accepts it
+    pub pending_authority: Option<Pubkey>,
+    // optional M-of-N signer set which has to co-sign the sensitive admin instructions
+    pub multisig: Option<Multisig>,
+    // keys of the roles granted by the owner
+    pub roles: Roles,
+    // bitmask of the paused instruction groups, see the PAUSE_* flags
+    pub paused: u16,
+    // address the treasury is swept to, the owner at the initialization
+    pub payout_address: Pubkey,
+    // minimum delay in milliseconds between queueing a fee or role change and applying it, at least MIN_CONFIG_DELAY
+    pub config_delay: i64,
+    // fee and role changes waiting for the config delay to pass
+    pub pending_config: Option<PendingConfig>,
+    // timestamp in milliseconds the owner started winding down the main state, no new periods or deposits from then on
+    pub sunset_at: Option<i64>,
+    // number of the subscription accounts of the main state, it can be closed only without any
+    pub open_subscriptions: u64,
+    // key signing the discount coupons redeemed on activation, no coupons when none
+    pub coupon_signer: Option<Pubkey>,
+    // share in basis points of the debits withdrawn from the referred subscriptions, accrued to their referrers
+    pub referral_bps: u16,
+    // milliseconds the access is kept after the end of the renewable period, waiting for the renewal
+    pub grace_period: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, InitSpace)]
+pub struct PendingConfig {
+    pub fee_schedule: Option<FeeSchedule>,
+    pub roles: Option<Roles>,
+    pub config_delay: Option<i64>,
+    pub renew_bounty_lamports: Option<u64>,
+    // timestamp in milliseconds from which the changes can be applied
+    pub effective_at: i64,
+}
+
+// lower bound of the config delay in milliseconds, so the subscribers always have a day to react to a queued change
+pub const MIN_CONFIG_DELAY: i64 = 1000 * 60 * 60 * 24;
+
+// cap of the renew bounty in basis points of the price of the renewed plan
+pub const MAX_RENEW_BOUNTY_BPS: u16 = 500;
+
+// instruction groups which can be paused separately
+pub const PAUSE_SUBSCRIBE: u16 = 1 << 0; // create_subscription, change_desired_subscription_type, set_auto_renew, set_cancel_at_period_end
+pub const PAUSE_FUND: u16 = 1 << 1; // fund_subscription
+pub const PAUSE_ACTIVATE: u16 = 1 << 2; // activate_subscription, renew, change_plan
+pub const PAUSE_SET_INFO: u16 = 1 << 3; // set_subscription_info, set_suspended, record_usage
+pub const PAUSE_UNSUBSCRIBE: u16 = 1 << 4; // unsubscribe, close_subscription
+pub const PAUSE_WITHDRAW: u16 = 1 << 5; // withdraw
+pub const PAUSE_PLANS: u16 = 1 << 6; // create_plan, update_plan, retire_plan
+pub const PAUSE_TREASURY: u16 = 1 << 7; // sweep_treasury, claim_referral_rewards
+pub const PAUSE_SUNSET: u16 = 1 << 8; // process_sunset
+pub const PAUSE_ALL: u16 = (1 << 9) - 1;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, InitSpace)]
+pub struct FeeSchedule {
+    // fee in basis points taken from the credits paid out on unsubscribe with withdrawal
+    pub withdrawal_bps: u16,
+    // fee in basis points taken from the unused debits moved back to credits on unsubscribe without withdrawal (switching the plan)
+    pub downgrade_bps: u16,
+    // fee in basis points taken from the unused debits of the running period terminated by unsubscribe with withdrawal
+    pub early_termination_bps: u16,
+    // flat minimum of the fees above, 0 for none. The fee never exceeds the amount it is taken from
+    pub min_withdrawal_fee: u64,
+    pub min_downgrade_fee: u64,
+    pub min_early_termination_fee: u64,
+}
+
+impl FeeSchedule {
+    /**
+     * Fails when any of the fees is over 100%.
+     */
+    pub fn validate(&self) -> Result<()> {
+        if [self.withdrawal_bps, self.downgrade_bps, self.early_termination_bps].iter().any(|bps| *bps as u64 > BPS_DENOMINATOR) {
+            return Err(MainStateError::InvalidFeeSchedule.into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, InitSpace)]
+pub struct Roles {
+    // verifies the subscriptions on behalf of the backend: sets their info, suspends them and records their usage
+    pub verifier: Option<Pubkey>,
+    // withdraws the earned funds of the subscriptions
+    pub treasurer: Option<Pubkey>,
+    // changes the unsubscribe fee and the renew bounty
+    pub fee_manager: Option<Pubkey>,
+    // pauses the program operations
+    pub pauser: Option<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Verifier,
+    Treasurer,
+    FeeManager,
+    Pauser,
+}
+
+// maximum number of the multisig signers, bounds the main state space
+pub const MAX_MULTISIG_SIGNERS: usize = 5;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, InitSpace)]
+pub struct Multisig {
+    #[max_len(MAX_MULTISIG_SIGNERS)]
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+}
+
+impl MainState {
+    /**
+     * Returns the pending config to queue a change into, restarting its delay from now.
+     */
+    pub fn queue_config(&mut self, now: i64) -> Result<&mut PendingConfig> {
+        let effective_at = now.checked_add(self.config_delay).ok_or(SubscriptionError::AirthemticError)?;
+        let pending_config = self.pending_config.get_or_insert_with(PendingConfig::default);
+        pending_config.effective_at = effective_at;
+        Ok(pending_config)
+    }
+
+    /**
+     * Applies the queued changes once their effective time has passed, clearing the pending config.
+     */
+    pub fn apply_pending_config(&mut self, now: i64) -> Result<()> {
+        match &self.pending_config {
+            Some(pending_config) if now < pending_config.effective_at => return Err(MainStateError::ConfigNotEffective.into()),
+            Some(_) => {},
+            None => return Err(MainStateError::NoPendingProposal.into()),
+        }
+        let pending_config = self.pending_config.take().ok_or(MainStateError::NoPendingProposal)?;
+        if let Some(fee_schedule) = pending_config.fee_schedule {
+            self.fee_schedule = fee_schedule;
+        }
+        if let Some(roles) = pending_config.roles {
+            self.roles = roles;
+        }
+        if let Some(config_delay) = pending_config.config_delay {
+            self.config_delay = config_delay;
+        }
+        if let Some(renew_bounty_lamports) = pending_config.renew_bounty_lamports {
+            self.renew_bounty_lamports = renew_bounty_lamports;
+        }
+        Ok(())
+    }
+
+    /**
+     * Returns true when the given key holds the given role.
+     */
+    pub fn has_role(&self, role: Role, key: &Pubkey) -> bool {
+        let holder = match role {
+            Role::Verifier => self.roles.verifier,
+            Role::Treasurer => self.roles.treasurer,
+            Role::FeeManager => self.roles.fee_manager,
+            Role::Pauser => self.roles.pauser,
+        };
+        holder.as_ref() == Some(key)
+    }
+
+    /**
+     * Fails when the given instruction group is paused.
+     */
+    pub fn check_not_paused(&self, flag: u16) -> Result<()> {
+        if self.paused & flag != 0 {
+            return Err(MainStateError::InstructionPaused.into());
+        }
+        Ok(())
+    }
+
+    /**
+     * Fails when the main state is being sunset.
+     */
+    pub fn check_not_sunset(&self) -> Result<()> {
+        if self.sunset_at.is_some() {
+            return Err(MainStateError::SunsetStarted.into());
+        }
+        Ok(())
+    }
+
+    /**
+     * Checks that at least the threshold of the multisig signers signed the transaction, either as the instruction signer or in the remaining accounts.
+     * Passes right away when no multisig is set.
+     */
+    pub fn check_multisig(&self, signer: &Pubkey, remaining_accounts: &[AccountInfo]) -> Result<()> {
+        let multisig = match &self.multisig {
+            Some(multisig) => multisig,
+            None => return Ok(()),
+        };
+        let approvals = multisig.signers.iter().filter(|listed| {
+            *listed == signer || remaining_accounts.iter().any(|account| account.is_signer && account.key == *listed)
+        }).count();
+        if approvals < multisig.threshold as usize {
+            return Err(MainStateError::MultisigThresholdNotMet.into());
+        }
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn main_state(owner: Pubkey) -> MainState {
+        MainState {
+            version: MAIN_STATE_VERSION,
+            owner,
+            authority: owner,
+            fee_schedule: FeeSchedule::default(),
+            tenant_id: 0,
+            bump: 0,
+            renew_bounty_lamports: 0,
+            accepted_mint: None,
+            pending_owner: None,
+            pending_authority: None,
+            multisig: None,
+            roles: Roles {
+                verifier: Some(owner),
+                treasurer: Some(owner),
+                fee_manager: Some(owner),
+                pauser: Some(owner),
+            },
+            paused: 0,
+            payout_address: owner,
+            config_delay: MIN_CONFIG_DELAY,
+            pending_config: None,
+            sunset_at: None,
+            open_subscriptions: 0,
+            coupon_signer: None,
+            referral_bps: 0,
+            grace_period: 0,
+        }
+    }
+
+    #[test]
+    fn pending_config_applies_only_from_effective_at() {
+        let owner = Pubkey::new_unique();
+        let fee_manager = Pubkey::new_unique();
+        let mut main_state = main_state(owner);
+        let fee_schedule = FeeSchedule { early_termination_bps: 1000, ..FeeSchedule::default() };
+        let roles = Roles { fee_manager: Some(fee_manager), treasurer: None, ..main_state.roles };
+        let pending_config = main_state.queue_config(1_000).unwrap();
+        pending_config.fee_schedule = Some(fee_schedule);
+        pending_config.roles = Some(roles);
+        pending_config.config_delay = Some(MIN_CONFIG_DELAY * 2);
+        pending_config.renew_bounty_lamports = Some(5_000);
+        let effective_at = 1_000 + MIN_CONFIG_DELAY;
+
+        assert_eq!(main_state.apply_pending_config(effective_at - 1).unwrap_err(), MainStateError::ConfigNotEffective.into());
+        assert_eq!(main_state.pending_config.as_ref().unwrap().effective_at, effective_at);
+        assert_eq!(main_state.fee_schedule, FeeSchedule::default());
+        assert!(main_state.has_role(Role::FeeManager, &owner));
+
+        main_state.apply_pending_config(effective_at).unwrap();
+        assert!(main_state.pending_config.is_none());
+        assert_eq!(main_state.fee_schedule, fee_schedule);
+        assert_eq!(main_state.config_delay, MIN_CONFIG_DELAY * 2);
+        assert_eq!(main_state.renew_bounty_lamports, 5_000);
+        // the granted fee manager takes over, the revoked treasurer loses the role
+        assert!(main_state.has_role(Role::FeeManager, &fee_manager));
+        assert!(!main_state.has_role(Role::FeeManager, &owner));
+        assert!(!main_state.has_role(Role::Treasurer, &owner));
+        assert_eq!(main_state.apply_pending_config(effective_at).unwrap_err(), MainStateError::NoPendingProposal.into());
+    }
+}
@@ -1,2 +1,8 @@
+pub mod coupon;
+pub mod legacy;
 pub mod main_state;
-pub mod subscription;
\ No newline at end of file
+pub mod plan;
+pub mod referral;
+pub mod subscription;
+pub mod treasury;
+pub mod trial;
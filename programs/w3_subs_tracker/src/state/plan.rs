@@ -0,0 +1,26 @@
+use anchor_lang::prelude::*;
+
+pub const MAX_PLAN_NAME_LEN: usize = 32;
+
+#[account]
+#[derive(InitSpace)]
+pub struct Plan {
+    pub main_state: Pubkey,
+    pub plan_id: u64,
+    #[max_len(MAX_PLAN_NAME_LEN)]
+    pub name: String,
+    pub price_lamports: u64,
+    // length of one subscription period in milliseconds
+    pub period: i64,
+    // ordering of the plans (higher tier => more access), replacing the former FREE < BASIC < PREMIUM ordering
+    pub tier: u8,
+    // retired plans can't be chosen for new subscription periods, already running periods are not affected
+    pub active: bool,
+    pub bump: u8,
+    // length of the free trial of the plan in milliseconds, granted once per wallet and main state, 0 for no trial
+    pub trial_period: i64,
+    // price of one unit of the metered usage recorded by the verifier, 0 for the plan without metering
+    pub unit_price: u64,
+    // maximum of the units recorded in one period, no cap when none
+    pub usage_cap: Option<u64>,
+}
@@ -0,0 +1,15 @@
+use anchor_lang::prelude::*;
+
+/**
+ * Referral rewards of the referrer in the main state. The funds are held by the treasury until claimed.
+ */
+#[account]
+#[derive(InitSpace)]
+pub struct ReferrerBalance {
+    pub main_state: Pubkey,
+    pub referrer: Pubkey,
+    // total referral share of the withdrawn debits of the referred subscriptions
+    pub accrued: u64,
+    pub claimed: u64,
+    pub bump: u8,
+}
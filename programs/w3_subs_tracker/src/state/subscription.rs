@@ -3,38 +3,157 @@
 
 use anchor_lang::{prelude::*};
 
+use crate::errors::error::SubscriptionError;
+use crate::state::main_state::FeeSchedule;
 
 
 
+
+// layout version of the subscription, legacy subscriptions without the version field are version 0
+pub const SUBSCRIPTION_VERSION: u8 = 1;
+
 #[account]
+#[derive(InitSpace)]
 pub struct Subscription {
+    pub version: u8,
     pub imutable_initialized: MutableInitialized,
     pub subscription_status_writable: CurrentSubscriptionStatistics,
     pub authority_writable: AuthorityWritable,
+    pub ledger: Ledger,
+    // fee schedule of the main state in force when the running period (or the subscription without any) started, used for the refund of its unused part
+    // and capping the withdrawal fee, so a raised fee never applies before the next period
+    pub fee_schedule: FeeSchedule,
+    // set once the user started the free trial, see the TrialRecord for the record outliving the closed subscription
+    pub trial_used: bool,
+    // lifecycle status, the time driven transitions are applied by settle
+    pub status: SubscriptionStatus,
+    // metered usage of the running period, reset when a new period starts
+    pub usage: Usage,
+}
+
+/**
+ * Metered usage recorded by the verifier in one period, charged from the credits at the unit price of the plan.
+ */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Default, InitSpace)]
+pub struct Usage {
+    // start of the period the usage is counted for
+    pub period_start: i64,
+    pub units: u64,
+    // credits moved to earned for the units of the period
+    pub charged: u64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
-pub enum SubscriptionType {
-    FREE,
-    BASIC,
-    PREMIUM,
+/**
+ * Lifecycle of the subscription. Ending periods move Active to Grace (for the grace period of the main state) and to Expired,
+ * CancelAtPeriodEnd straight to Expired. Suspended is entered and lifted only by the verifier.
+ */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, Default, InitSpace)]
+pub enum SubscriptionStatus {
+    // created, no period started yet
+    #[default]
+    Pending,
+    // running period, renewed by the keeper when auto renewing
+    Active,
+    // renewable period ended, access is kept until the end of the grace period
+    Grace,
+    // running period which won't be renewed
+    CancelAtPeriodEnd,
+    // no running period
+    Expired,
+    // blocked by the verifier, no access and no new periods
+    Suspended,
 }
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+
+impl SubscriptionStatus {
+    /**
+     * Valid transitions of the instructions, lifting the suspension is not one of them.
+     */
+    pub fn can_transition_to(self, to: SubscriptionStatus) -> bool {
+        use SubscriptionStatus::*;
+        match (self, to) {
+            (Suspended, _) => false,
+            (_, Suspended) | (_, Expired) | (_, Active) => true,
+            (Active, Grace) | (Active, CancelAtPeriodEnd) => true,
+            (from, to) => from == to,
+        }
+    }
+}
+
+/**
+ * Buckets of the funds held by the subscription. Funds held above their sum (e.g. direct transfers to the PDA) belong to nobody.
+ */
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Default, InitSpace)]
+pub struct Ledger {
+    // deposited funds not assigned to any period yet, owned by the user
+    pub credits: u64,
+    // price of the running period, earned once the period ends
+    pub locked: u64,
+    // debits of the finished periods, the only funds the authority can withdraw
+    pub earned: u64,
+    // unsubscribe fees owed to the treasury of the main state
+    pub fees_owed: u64,
+    // total of the earned funds already withdrawn by the authority
+    pub withdrawn: u64,
+}
+
+impl Subscription {
+    /**
+     * Moves the locked debits of the finished period to the earned ones and applies the time driven status transitions.
+     */
+    pub fn settle(&mut self, now: i64, grace_period: i64) -> Result<()> {
+        if self.authority_writable.valid_till <= now && self.ledger.locked > 0 {
+            self.ledger.earned = self.ledger.earned.checked_add(self.ledger.locked).ok_or(SubscriptionError::AirthemticError)?;
+            self.ledger.locked = 0;
+        }
+        self.status = self.status_at(now, grace_period);
+        Ok(())
+    }
+
+    /**
+     * Status at the given time, without storing it.
+     */
+    pub fn status_at(&self, now: i64, grace_period: i64) -> SubscriptionStatus {
+        let valid_till = self.authority_writable.valid_till;
+        match self.status {
+            SubscriptionStatus::Active | SubscriptionStatus::CancelAtPeriodEnd if valid_till > now => self.status,
+            SubscriptionStatus::Active | SubscriptionStatus::Grace if valid_till.saturating_add(grace_period) > now => SubscriptionStatus::Grace,
+            SubscriptionStatus::Active | SubscriptionStatus::Grace | SubscriptionStatus::CancelAtPeriodEnd => SubscriptionStatus::Expired,
+            status => status,
+        }
+    }
+
+    /**
+     * Moves the subscription to the given status, failing for the transitions not allowed.
+     */
+    pub fn transition(&mut self, to: SubscriptionStatus) -> Result<()> {
+        if !self.status.can_transition_to(to) {
+            return Err(SubscriptionError::InvalidStatusTransition.into());
+        }
+        self.status = to;
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, InitSpace)]
 pub struct CurrentSubscriptionStatistics {
-    pub after_verify_credit_lamports: u64,
     pub after_verify_utc_timestamp: i64,
-    pub desired_subscription_type: SubscriptionType,
+    // plan account the user wants to be subscribed to, Pubkey::default() if none
+    pub desired_plan: Pubkey,
+    // opt-in flag letting anyone renew the expired current plan from the credits
+    pub auto_renew: bool,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, InitSpace)]
 pub struct AuthorityWritable {
-    pub current_account_type: SubscriptionType,
+    // plan account of the current subscription period, Pubkey::default() if none
+    pub current_plan: Pubkey,
     pub valid_till: i64,
-    pub used_lamports: u64
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, InitSpace)]
 pub struct MutableInitialized {
     pub main_state_pda: Pubkey,
     pub user: Pubkey,
+    // wallet which referred the user, earns the referral share of the withdrawn debits
+    pub referrer: Option<Pubkey>,
 }
\ No newline at end of file
@@ -0,0 +1,14 @@
+use anchor_lang::prelude::*;
+
+#[account]
+#[derive(InitSpace)]
+pub struct Treasury {
+    pub main_state: Pubkey,
+    pub bump: u8,
+    // total of the fees and the earned revenue moved into the treasury
+    pub collected: u64,
+    // total swept to the payout address of the main state
+    pub swept: u64,
+    // referral rewards held by the treasury until their referrers claim them, never swept
+    pub referral_owed: u64,
+}
@@ -0,0 +1,14 @@
+use anchor_lang::prelude::*;
+
+/**
+ * Record of the free trial started by the wallet in the main state, kept after the subscription is closed so the trial can't be repeated.
+ */
+#[account]
+#[derive(InitSpace)]
+pub struct TrialRecord {
+    pub main_state: Pubkey,
+    pub user: Pubkey,
+    pub plan: Pubkey,
+    pub started_at: i64,
+    pub bump: u8,
+}
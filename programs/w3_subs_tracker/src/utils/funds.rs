@@ -0,0 +1,229 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
+
+use crate::errors::error::SubscriptionError;
+use crate::state::main_state::MainState;
+
+/**
+ * Unpacks the token account, checking it is owned by the token program.
+ */
+pub fn unpack_token_account(account: &AccountInfo) -> Result<TokenAccount> {
+    if account.owner != &token::ID {
+        return Err(SubscriptionError::InvalidTokenAccount.into());
+    }
+    TokenAccount::try_deserialize(&mut &account.try_borrow_data()?[..])
+}
+
+/**
+ * Unpacks the mint, checking it is owned by the token program. Returns the mint address.
+ */
+pub fn unpack_mint(account: &AccountInfo) -> Result<Pubkey> {
+    if account.owner != &token::ID {
+        return Err(SubscriptionError::InvalidMint.into());
+    }
+    Mint::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+    Ok(account.key())
+}
+
+/**
+ * Where the funds of a subscription are held. Main state without accepted mint works with the lamports of the subscription PDA,
+ * main state with accepted mint works with the tokens of the subscription vault (token account owned by the subscription PDA).
+ */
+pub enum Funds<'a, 'info> {
+    Lamports,
+    Tokens {
+        vault: &'a AccountInfo<'info>,
+        mint: Pubkey,
+        token_program: &'a Program<'info, Token>,
+    },
+}
+
+impl<'a, 'info> Funds<'a, 'info> {
+    pub fn resolve(main_state: &MainState, vault: &'a Option<UncheckedAccount<'info>>, token_program: &'a Option<Program<'info, Token>>) -> Result<Self> {
+        Self::resolve_vault(main_state, vault.as_deref(), token_program)
+    }
+
+    /**
+     * Same as resolve, for the vault passed outside of the instruction accounts (e.g. in the remaining accounts).
+     */
+    pub fn resolve_vault(main_state: &MainState, vault: Option<&'a AccountInfo<'info>>, token_program: &'a Option<Program<'info, Token>>) -> Result<Self> {
+        let mint = match main_state.accepted_mint {
+            Some(mint) => mint,
+            None => return Ok(Funds::Lamports),
+        };
+        match (vault, token_program) {
+            (Some(vault), Some(token_program)) => {
+                let vault_account = unpack_token_account(vault)?;
+                if vault_account.mint != mint {
+                    return Err(SubscriptionError::InvalidMint.into());
+                }
+                Ok(Funds::Tokens { vault, mint, token_program })
+            },
+            _ => Err(SubscriptionError::MissingObligatoryAccount.into()),
+        }
+    }
+
+    /**
+     * Moves the amount from the signer (or his token account) to the subscription.
+     */
+    pub fn deposit(&self, from: &Signer<'info>, from_token: Option<&UncheckedAccount<'info>>, subscription: &AccountInfo<'info>, system_program: &Program<'info, System>, amount: u64) -> Result<()> {
+        match self {
+            Funds::Lamports => {
+                let cpi_accounts = Transfer {
+                    from: from.to_account_info(),
+                    to: subscription.clone(),
+                };
+                let cpi_ctx = CpiContext::new(system_program.to_account_info(), cpi_accounts);
+                system_program::transfer(cpi_ctx, amount)
+            },
+            Funds::Tokens { vault, token_program, .. } => {
+                // token program checks the mint and the authority of the source account
+                let from_token = from_token.ok_or(SubscriptionError::MissingObligatoryAccount)?;
+                let cpi_accounts = token::Transfer {
+                    from: from_token.to_account_info(),
+                    to: (*vault).clone(),
+                    authority: from.to_account_info(),
+                };
+                let cpi_ctx = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+                token::transfer(cpi_ctx, amount)
+            },
+        }
+    }
+
+    /**
+     * Moves the amount from the subscription (or the treasury) to the given account (or its token account), signed by the source PDA.
+     */
+    pub fn pay_out(&self, subscription: &AccountInfo<'info>, signer_seeds: &[&[&[u8]]], to: &AccountInfo<'info>, to_token: Option<&UncheckedAccount<'info>>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        match self {
+            Funds::Lamports => {
+                **subscription.try_borrow_mut_lamports()? -= amount;
+                **to.try_borrow_mut_lamports()? += amount;
+                Ok(())
+            },
+            Funds::Tokens { vault, mint, token_program, .. } => {
+                let to_token = to_token.ok_or(SubscriptionError::MissingObligatoryAccount)?;
+                let to_token_account = unpack_token_account(to_token)?;
+                if to_token_account.mint != *mint {
+                    return Err(SubscriptionError::InvalidMint.into());
+                }
+                // the token account must belong to the receiver the lamports would be sent to
+                if to_token_account.owner != to.key() {
+                    return Err(SubscriptionError::InvalidTokenAccount.into());
+                }
+                let cpi_accounts = token::Transfer {
+                    from: (*vault).clone(),
+                    to: to_token.to_account_info(),
+                    authority: subscription.clone(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer_seeds);
+                token::transfer(cpi_ctx, amount)
+            },
+        }
+    }
+
+    /**
+     * Tokens held by the vault above the given amount tracked by the ledger, e.g. sent to the vault directly. Always 0 for lamports,
+     * which go with the rent of the closed account.
+     */
+    pub fn excess(&self, tracked: u64) -> Result<u64> {
+        match self {
+            Funds::Lamports => Ok(0),
+            Funds::Tokens { vault, .. } => Ok(unpack_token_account(vault)?.amount.saturating_sub(tracked)),
+        }
+    }
+
+    /**
+     * Closes the vault of the subscription (or the treasury), no-op for lamports, returning its rent to the given account.
+     * The vault must be empty, so its excess has to be paid out first.
+     */
+    pub fn close_vault(&self, subscription: &AccountInfo<'info>, signer_seeds: &[&[&[u8]]], to: &AccountInfo<'info>) -> Result<()> {
+        match self {
+            Funds::Lamports => Ok(()),
+            Funds::Tokens { vault, token_program, .. } => {
+                let cpi_accounts = token::CloseAccount {
+                    account: (*vault).clone(),
+                    destination: to.clone(),
+                    authority: subscription.clone(),
+                };
+                let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer_seeds);
+                token::close_account(cpi_ctx)
+            },
+        }
+    }
+}
+
+/**
+ * Closes the program owned account, moving all its lamports to the destination.
+ */
+pub fn close_program_account<'info>(account: &AccountInfo<'info>, destination: &AccountInfo<'info>) -> Result<()> {
+    let lamports = account.lamports();
+    **destination.try_borrow_mut_lamports()? = destination.lamports().checked_add(lamports).ok_or(SubscriptionError::AirthemticError)?;
+    **account.try_borrow_mut_lamports()? = 0;
+    account.assign(&system_program::ID);
+    account.realloc(0, false).map_err(Into::into)
+}
+
+/**
+ * Creates the account at the PDA address signed by the given seeds, owned by the given program. Same as the init constraint, the address
+ * funded by anyone beforehand would fail create_account, so it is topped up to the rent exempt balance, allocated and assigned instead.
+ */
+pub fn create_pda_account<'info>(payer: &Signer<'info>, account: &AccountInfo<'info>, signer_seeds: &[&[&[u8]]], space: usize, owner: &Pubkey, system_program: &Program<'info, System>) -> Result<()> {
+    let rent_exempt = Rent::get()?.minimum_balance(space);
+    if account.lamports() == 0 {
+        let cpi_accounts = system_program::CreateAccount {
+            from: payer.to_account_info(),
+            to: account.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer_seeds);
+        return system_program::create_account(cpi_ctx, rent_exempt, space as u64, owner);
+    }
+    let top_up = rent_exempt.saturating_sub(account.lamports());
+    if top_up > 0 {
+        let cpi_accounts = Transfer {
+            from: payer.to_account_info(),
+            to: account.clone(),
+        };
+        system_program::transfer(CpiContext::new(system_program.to_account_info(), cpi_accounts), top_up)?;
+    }
+    let cpi_accounts = system_program::Allocate {
+        account_to_allocate: account.clone(),
+    };
+    system_program::allocate(CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer_seeds), space as u64)?;
+    let cpi_accounts = system_program::Assign {
+        account_to_assign: account.clone(),
+    };
+    system_program::assign(CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer_seeds), owner)
+}
+
+/**
+ * Creates the token account of the given mint at the given PDA address, owned by the given authority. Lamports sent to the address
+ * beforehand can't block it.
+ */
+pub fn create_token_account<'info>(payer: &Signer<'info>, account: &UncheckedAccount<'info>, account_seeds: &[&[u8]], mint: &AccountInfo<'info>, authority: &AccountInfo<'info>, system_program: &Program<'info, System>, token_program: &Program<'info, Token>) -> Result<()> {
+    create_pda_account(payer, &account.to_account_info(), &[account_seeds], TokenAccount::LEN, token_program.key, system_program)?;
+    let cpi_accounts = token::InitializeAccount3 {
+        account: account.to_account_info(),
+        mint: mint.clone(),
+        authority: authority.clone(),
+    };
+    token::initialize_account3(CpiContext::new(token_program.to_account_info(), cpi_accounts))
+}
+
+/**
+ * Creates the vault token account of the subscription PDA at the vault PDA address and moves the initial deposit into it.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn create_vault<'info>(payer: &Signer<'info>, payer_token: Option<&UncheckedAccount<'info>>, vault: &UncheckedAccount<'info>, vault_seeds: &[&[u8]], mint: &UncheckedAccount<'info>, subscription: &AccountInfo<'info>, system_program: &Program<'info, System>, token_program: &Program<'info, Token>, initial_deposit: u64) -> Result<()> {
+    create_token_account(payer, vault, vault_seeds, &mint.to_account_info(), subscription, system_program, token_program)?;
+    let payer_token = payer_token.ok_or(SubscriptionError::MissingObligatoryAccount)?;
+    let cpi_accounts = token::Transfer {
+        from: payer_token.to_account_info(),
+        to: vault.to_account_info(),
+        authority: payer.to_account_info(),
+    };
+    token::transfer(CpiContext::new(token_program.to_account_info(), cpi_accounts), initial_deposit)
+}
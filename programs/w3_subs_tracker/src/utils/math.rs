@@ -0,0 +1,91 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::error::SubscriptionError;
+
+// 100% in basis points
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/**
+ * amount * numerator / denominator computed in u128, rounded down.
+ */
+pub fn mul_div(amount: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    if denominator == 0 {
+        return Err(SubscriptionError::AirthemticError.into());
+    }
+    let result = (amount as u128)
+        .checked_mul(numerator as u128)
+        .ok_or(SubscriptionError::AirthemticError)?
+        / denominator as u128;
+    u64::try_from(result).map_err(|_| SubscriptionError::AirthemticError.into())
+}
+
+/**
+ * Fee in basis points of the amount, rounded down, raised to the flat minimum fee but never over the amount itself.
+ */
+pub fn fee_bps(amount: u64, fee_bps: u16, min_fee: u64) -> Result<u64> {
+    let fee = mul_div(amount, fee_bps as u64, BPS_DENOMINATOR)?;
+    Ok(fee.max(min_fee).min(amount))
+}
+
+/**
+ * Part of the used lamports not consumed yet by the running period (period_start..valid_till) at the time now.
+ * Expired periods give 0, periods not started yet give the whole amount.
+ */
+pub fn unused_part(used_lamports: u64, period_start: i64, valid_till: i64, now: i64) -> Result<u64> {
+    if valid_till <= now || valid_till <= period_start {
+        return Ok(0);
+    }
+    let period = valid_till.checked_sub(period_start).ok_or(SubscriptionError::AirthemticError)?;
+    let remaining = valid_till.checked_sub(now).ok_or(SubscriptionError::AirthemticError)?.min(period);
+    mul_div(used_lamports, remaining as u64, period as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_rounds_down_in_u128() {
+        assert_eq!(mul_div(10, 1, 3).unwrap(), 3);
+        assert_eq!(mul_div(u64::MAX, u64::MAX, u64::MAX).unwrap(), u64::MAX);
+        assert_eq!(mul_div(u64::MAX, 3, 4).unwrap(), 13_835_058_055_282_163_711);
+    }
+
+    #[test]
+    fn mul_div_fails_on_zero_denominator_and_overflow() {
+        assert!(mul_div(1, 1, 0).is_err());
+        assert!(mul_div(u64::MAX, 2, 1).is_err());
+    }
+
+    #[test]
+    fn fee_bps_applies_the_minimum_up_to_the_amount() {
+        assert_eq!(fee_bps(1_000_000, 1000, 0).unwrap(), 100_000);
+        assert_eq!(fee_bps(999, 1, 0).unwrap(), 0);
+        assert_eq!(fee_bps(1_000_000, 1000, 200_000).unwrap(), 200_000);
+        assert_eq!(fee_bps(50, 1000, 200).unwrap(), 50);
+        assert_eq!(fee_bps(1_000_000, BPS_DENOMINATOR as u16, 0).unwrap(), 1_000_000);
+        assert_eq!(fee_bps(0, 1000, 200).unwrap(), 0);
+    }
+
+    #[test]
+    fn unused_part_is_prorated_over_the_period() {
+        assert_eq!(unused_part(1_000, 0, 100, 25).unwrap(), 750);
+        assert_eq!(unused_part(1_000, 0, 3, 1).unwrap(), 666);
+        // not started yet, the whole amount
+        assert_eq!(unused_part(1_000, 100, 200, 50).unwrap(), 1_000);
+        assert_eq!(unused_part(u64::MAX, 0, i64::MAX, 0).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn unused_part_is_zero_for_ended_or_empty_periods() {
+        assert_eq!(unused_part(1_000, 0, 100, 100).unwrap(), 0);
+        assert_eq!(unused_part(1_000, 0, 100, 150).unwrap(), 0);
+        assert_eq!(unused_part(1_000, 100, 100, 50).unwrap(), 0);
+        assert_eq!(unused_part(1_000, 200, 100, 50).unwrap(), 0);
+    }
+
+    #[test]
+    fn unused_part_fails_when_the_period_overflows() {
+        assert!(unused_part(1_000, i64::MIN, i64::MAX, 0).is_err());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod funds;
+pub mod math;
+pub mod signature;
@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+use solana_program::ed25519_program;
+use solana_program::sysvar::instructions::{load_current_index_checked, load_instruction_at_checked};
+
+use crate::errors::error::CouponError;
+
+// layout of the ed25519 program instruction data with a single signature
+const SIGNATURE_OFFSETS_START: usize = 2;
+const SIGNATURE_OFFSETS_LEN: usize = 14;
+const PUBKEY_LEN: usize = 32;
+// instruction index of the offsets pointing to the ed25519 instruction itself
+const CURRENT_INSTRUCTION: u16 = u16::MAX;
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(CouponError::InvalidSignature.into())
+}
+
+/**
+ * Checks that the instruction right before the current one is the ed25519 program verifying the signature of the given message by the given signer.
+ * The precompile fails the whole transaction on an invalid signature, so only the signer and the message have to be compared.
+ */
+pub fn verify_ed25519(instructions: &AccountInfo, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    let current = load_current_index_checked(instructions)?;
+    if current == 0 {
+        return Err(CouponError::MissingSignature.into());
+    }
+    let instruction = load_instruction_at_checked(current as usize - 1, instructions)?;
+    if instruction.program_id != ed25519_program::ID {
+        return Err(CouponError::MissingSignature.into());
+    }
+    let data = &instruction.data;
+    if data.len() < SIGNATURE_OFFSETS_START + SIGNATURE_OFFSETS_LEN || data[0] != 1 {
+        return Err(CouponError::InvalidSignature.into());
+    }
+    let offsets = SIGNATURE_OFFSETS_START;
+    // signature, public key and message must all be in the ed25519 instruction itself
+    for index_offset in [2, 6, 12] {
+        if read_u16(data, offsets + index_offset)? != CURRENT_INSTRUCTION {
+            return Err(CouponError::InvalidSignature.into());
+        }
+    }
+    let pubkey_offset = read_u16(data, offsets + 4)? as usize;
+    let message_offset = read_u16(data, offsets + 8)? as usize;
+    let message_len = read_u16(data, offsets + 10)? as usize;
+    let signed_pubkey = data.get(pubkey_offset..pubkey_offset + PUBKEY_LEN).ok_or(CouponError::InvalidSignature)?;
+    let signed_message = data.get(message_offset..message_offset + message_len).ok_or(CouponError::InvalidSignature)?;
+    if signed_pubkey != signer.as_ref() || signed_message != message {
+        return Err(CouponError::InvalidSignature.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
+import { LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
+
+
+describe("changePlan", () => {
+    const provider = anchor.AnchorProvider.env()
+
+    anchor.setProvider(provider);
+
+    const idl = require("../target/idl/w_3_subs_tracker.json") as any;
+    const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    const tenantId = new BN(Date.now());
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const planPda = (planId: BN) => anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), planId.toArrayLike(Buffer, 'le', 8)], program.programId)[0];
+    const basicPDA = planPda(new BN(1));
+    const premiumPDA = planPda(new BN(2));
+    const basicPrice = new BN(LAMPORTS_PER_SOL * 0.1);
+    const premiumPrice = new BN(LAMPORTS_PER_SOL * 0.2);
+    const user = anchor.web3.Keypair.generate();
+    const [subsPDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId);
+    // tolerance for the part of the period consumed between the transactions
+    const tolerance = LAMPORTS_PER_SOL * 0.0001;
+
+    it("Should init main state, two tiers and an active basic subscription", async () => {
+        await program.methods
+            .intializeMainState(tenantId, feeSchedule, configDelay)
+            .rpc();
+        await program.methods
+            .createPlan(new BN(1), "basic", basicPrice, new BN(1000 * 60 * 60 * 24 * 30), 1)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        await program.methods
+            .createPlan(new BN(2), "premium", premiumPrice, new BN(1000 * 60 * 60 * 24 * 30), 2)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        const tx = await provider.connection.requestAirdrop(user.publicKey, LAMPORTS_PER_SOL);
+        await provider.connection.confirmTransaction(tx);
+        await program.methods
+            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.5), null)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: basicPDA})
+            .signers([user])
+            .rpc();
+        await program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: basicPDA})
+            .signers([user])
+            .rpc();
+    });
+
+    it("Shouldn't change to the current plan or from other than the current plan", async () => {
+        let err = null;
+        try {
+            await program.methods
+                .changePlan()
+                .accounts({mainState: mainStatePDA, user: user.publicKey, currentPlan: basicPDA, newPlan: basicPDA})
+                .signers([user])
+                .rpc();
+            err = "Plan was changed to the current plan";
+        } catch (e) {
+            if (!e.toString().includes("SamePlan")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+        try {
+            await program.methods
+                .changePlan()
+                .accounts({mainState: mainStatePDA, user: user.publicKey, currentPlan: premiumPDA, newPlan: basicPDA})
+                .signers([user])
+                .rpc();
+            err = "Plan was changed from other than the current plan";
+        } catch (e) {
+            if (!e.toString().includes("NotCurrentPlan")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+    });
+
+    it("Should upgrade without fee, crediting the unused period and keeping the subscription active", async () => {
+        const before = await program.account.subscription.fetch(subsPDA);
+        await program.methods
+            .changePlan()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, currentPlan: basicPDA, newPlan: premiumPDA})
+            .signers([user])
+            .rpc();
+        const after = await program.account.subscription.fetch(subsPDA);
+        if (after.authorityWritable.currentPlan.toBase58() !== premiumPDA.toBase58()) throw new Error("Plan is not changed");
+        if (after.authorityWritable.validTill.lt(before.authorityWritable.validTill)) throw new Error("Subscription is not continuously active");
+        if (!after.ledger.feesOwed.eqn(0)) throw new Error("Upgrade was charged a fee");
+        if (!after.ledger.locked.eq(premiumPrice)) throw new Error("New plan price is not charged");
+        const expectedCredits = before.ledger.credits.add(basicPrice).sub(premiumPrice);
+        if (expectedCredits.sub(after.ledger.credits).toNumber() > tolerance) throw new Error("Unused period is not credited");
+    });
+
+    it("Should downgrade with the downgrade fee taken from the unused period", async () => {
+        const before = await program.account.subscription.fetch(subsPDA);
+        await program.methods
+            .changePlan()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, currentPlan: premiumPDA, newPlan: basicPDA})
+            .signers([user])
+            .rpc();
+        const after = await program.account.subscription.fetch(subsPDA);
+        if (after.authorityWritable.currentPlan.toBase58() !== basicPDA.toBase58()) throw new Error("Plan is not changed");
+        const fee = premiumPrice.divn(20);
+        if (fee.sub(after.ledger.feesOwed).toNumber() > tolerance) throw new Error("Downgrade fee is not charged");
+        const expectedCredits = before.ledger.credits.add(premiumPrice).sub(fee).sub(basicPrice);
+        if (expectedCredits.sub(after.ledger.credits).toNumber() > tolerance) throw new Error("Unused period is not credited");
+    });
+});
@@ -1,2 +1,9 @@
+import { BN } from "bn.js";
+
 const shouldDebug = false;
-if(!shouldDebug) console.log = function() {};
\ No newline at end of file
+if(!shouldDebug) console.log = function() {};
+
+// 10% fee for terminating the period early, half of it for switching the plan
+export const feeSchedule = {withdrawalBps: 0, downgradeBps: 500, earlyTerminationBps: 1000, minWithdrawalFee: new BN(0), minDowngradeFee: new BN(0), minEarlyTerminationFee: new BN(0)};
+// notice period of the fee, bounty and role changes, 1 day is the minimum
+export const configDelay = new BN(1000 * 60 * 60 * 24);
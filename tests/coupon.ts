@@ -0,0 +1,110 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
+import { Ed25519Program, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
+
+
+describe("coupon", () => {
+    const provider = anchor.AnchorProvider.env()
+
+    anchor.setProvider(provider);
+
+    const idl = require("../target/idl/w_3_subs_tracker.json") as any;
+    const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    const tenantId = new BN(Date.now());
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const planId = new BN(Date.now());
+    const [planPDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), planId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const price = new BN(LAMPORTS_PER_SOL * 0.2);
+    const couponSigner = anchor.web3.Keypair.generate();
+    const users = [anchor.web3.Keypair.generate(), anchor.web3.Keypair.generate()];
+    const subsPdas = users.map(user => anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId)[0]);
+    // 50% off, usable once
+    const coupon = {
+        mainState: mainStatePDA,
+        plan: planPDA,
+        percentOffBps: 5000,
+        amountOff: new BN(0),
+        expiresAt: new BN(Date.now() + 1000 * 60 * 60),
+        maxUses: 1,
+        nonce: new BN(Date.now()),
+    };
+
+    // borsh serialization of the Coupon struct, the message signed by the coupon signer
+    function serializeCoupon(c: typeof coupon): Buffer {
+        const numbers = Buffer.alloc(2 + 8 + 8 + 4 + 8);
+        numbers.writeUInt16LE(c.percentOffBps, 0);
+        c.amountOff.toArrayLike(Buffer, 'le', 8).copy(numbers, 2);
+        c.expiresAt.toArrayLike(Buffer, 'le', 8).copy(numbers, 10);
+        numbers.writeUInt32LE(c.maxUses, 18);
+        c.nonce.toArrayLike(Buffer, 'le', 8).copy(numbers, 22);
+        return Buffer.concat([c.mainState.toBuffer(), c.plan.toBuffer(), numbers]);
+    }
+
+    async function activateWithCoupon(user: anchor.web3.Keypair, c: typeof coupon, signer: anchor.web3.Keypair) {
+        const signatureIx = Ed25519Program.createInstructionWithPrivateKey({privateKey: signer.secretKey, message: serializeCoupon(c)});
+        return program.methods
+            .activateWithCoupon(c)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .preInstructions([signatureIx])
+            .signers([user])
+            .rpc();
+    }
+
+    it("Should init the plan, the coupon signer and the subscriptions", async () => {
+        try {
+            await program.account.mainState.fetch(mainStatePDA);
+        } catch {
+            await program.methods
+                .intializeMainState(tenantId, feeSchedule, configDelay)
+                .rpc({skipPreflight: true});
+        }
+        await program.methods
+            .createPlan(planId, "promo", price, new BN(1000 * 60 * 60 * 24 * 30), 1)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        await program.methods
+            .setCouponSigner(couponSigner.publicKey)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        for (const user of users) {
+            const tx = await provider.connection.requestAirdrop(user.publicKey, LAMPORTS_PER_SOL);
+            await provider.connection.confirmTransaction(tx);
+            await program.methods
+                .createSubscription(price, null)
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+                .signers([user])
+                .rpc();
+        }
+    });
+
+    it("Shouldn't accept the coupon signed by other key", async () => {
+        let err = null;
+        try {
+            await activateWithCoupon(users[0], coupon, anchor.web3.Keypair.generate());
+            err = "Coupon signed by other key was accepted";
+        } catch (e) {
+            if (!e.toString().includes("InvalidSignature")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+    });
+
+    it("Should activate the plan for the discounted price", async () => {
+        await activateWithCoupon(users[0], coupon, couponSigner);
+        const subsInfo = await program.account.subscription.fetch(subsPdas[0]);
+        if (!subsInfo.ledger.locked.eq(price.divn(2)) || !subsInfo.ledger.credits.eq(price.divn(2))) throw new Error("Coupon discount was not applied");
+    });
+
+    it("Shouldn't let the coupon be replayed over its max uses", async () => {
+        let err = null;
+        try {
+            await activateWithCoupon(users[1], coupon, couponSigner);
+            err = "Coupon was redeemed over its max uses";
+        } catch (e) {
+            if (!e.toString().includes("CouponExhausted")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+    });
+});
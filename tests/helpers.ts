@@ -0,0 +1,11 @@
+// fails unless the call is rejected with the given error
+export async function expectError(call: Promise<any>, error: string) {
+    let err = null;
+    try {
+        await call;
+        err = `Expected ${error}`;
+    } catch (e) {
+        if (!e.toString().includes(error)) err = `Unexpected error: ${e}`;
+    }
+    if (err) throw new Error(err);
+}
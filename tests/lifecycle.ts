@@ -0,0 +1,180 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
+import { LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
+import { expectError } from "./helpers";
+
+
+describe("lifecycle", () => {
+    async function wait(ms: number) {
+        return new Promise(resolve => {
+            setTimeout(resolve, ms);
+        });
+    }
+    const provider = anchor.AnchorProvider.env()
+
+    anchor.setProvider(provider);
+
+    const idl = require("../target/idl/w_3_subs_tracker.json") as any;
+    const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    // own tenant, so the grace period doesn't affect the other tests
+    const tenantId = new BN(Date.now());
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const planId = new BN(1);
+    const [planPDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), planId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const price = new BN(LAMPORTS_PER_SOL * 0.1);
+    const period = 2000;
+    const gracePeriod = 60000;
+    const user = anchor.web3.Keypair.generate();
+    const keeper = anchor.web3.Keypair.generate();
+    const [subsPDA, ___] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId);
+    const status = async () => Object.keys((await program.account.subscription.fetch(subsPDA)).status)[0];
+    const verifyAccess = () => program.methods
+        .verifyAccess(1)
+        .accounts({mainState: mainStatePDA, plan: planPDA, user: user.publicKey})
+        .view();
+    // verifier call without a running period, which keeps a pending or suspended subscription as it is
+    const setInfoWithoutPeriod = () => program.methods
+        .setSubscriptionInfo(null, new BN(0))
+        .accounts({mainState: mainStatePDA, user: user.publicKey, plan: null})
+        .rpc();
+
+    it("Should init main state with grace period and pending subscription", async () => {
+        await program.methods
+            .intializeMainState(tenantId, feeSchedule, configDelay)
+            .rpc();
+        await expectError(program.methods
+            .setGracePeriod(new BN(-1))
+            .accounts({mainState: mainStatePDA})
+            .rpc(), "InvalidGracePeriod");
+        await program.methods
+            .setGracePeriod(new BN(gracePeriod))
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        await program.methods
+            .createPlan(planId, "short", price, new BN(period), 1)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        for (const keypair of [user, keeper]) {
+            const tx = await provider.connection.requestAirdrop(keypair.publicKey, LAMPORTS_PER_SOL);
+            await provider.connection.confirmTransaction(tx);
+        }
+        await program.methods
+            .createSubscription(price.muln(4), null)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc();
+        if (await status() !== "pending") throw new Error("Created subscription is not pending");
+        await setInfoWithoutPeriod();
+        if (await status() !== "pending") throw new Error("Subscription info without a period changed the pending status");
+        await expectError(program.methods
+            .setCancelAtPeriodEnd(true)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .signers([user])
+            .rpc(), "InvalidStatusTransition");
+    });
+
+    it("Should cancel the active period at its end and resume it", async () => {
+        await program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc();
+        if (await status() !== "active") throw new Error("Activated subscription is not active");
+        await program.methods
+            .setCancelAtPeriodEnd(true)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .signers([user])
+            .rpc();
+        if (await status() !== "cancelAtPeriodEnd") throw new Error("Subscription is not cancelled");
+        await expectError(program.methods
+            .setAutoRenew(true)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .signers([user])
+            .rpc(), "InvalidStatusTransition");
+        await program.methods
+            .setCancelAtPeriodEnd(false)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .signers([user])
+            .rpc();
+        if (await status() !== "active") throw new Error("Subscription is not resumed");
+        await program.methods
+            .setAutoRenew(true)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .signers([user])
+            .rpc();
+    });
+
+    it("Should keep the access during the grace period and renew from it", async () => {
+        await wait(period + 1000);
+        const subsInfo = await program.account.subscription.fetch(subsPDA);
+        const access = await verifyAccess();
+        if (!access.eq(subsInfo.authorityWritable.validTill.addn(gracePeriod))) throw new Error("Grace period doesn't extend the access");
+        await program.methods
+            .renew()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, keeper: keeper.publicKey})
+            .signers([keeper])
+            .rpc();
+        if (await status() !== "active") throw new Error("Renewed subscription is not active");
+    });
+
+    it("Should let only the authority suspend and lift the suspension", async () => {
+        await expectError(program.methods
+            .setSuspended(true)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, authority: user.publicKey})
+            .signers([user])
+            .rpc(), "InvalidAuthority");
+        await program.methods
+            .setSuspended(true)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .rpc();
+        if (await status() !== "suspended") throw new Error("Subscription is not suspended");
+        await expectError(verifyAccess(), "SubscriptionSuspended");
+        await setInfoWithoutPeriod();
+        if (await status() !== "suspended") throw new Error("Subscription info lifted the suspension");
+        await expectError(program.methods
+            .setCancelAtPeriodEnd(true)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .signers([user])
+            .rpc(), "InvalidStatusTransition");
+        await wait(period + 1000);
+        await expectError(program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc(), "InvalidStatusTransition");
+        await program.methods
+            .setSuspended(false)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .rpc();
+        // the period ended while suspended
+        if (await status() !== "expired") throw new Error("Lifted subscription is not expired");
+        await expectError(program.methods
+            .setSuspended(false)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .rpc(), "InvalidStatusTransition");
+    });
+
+    it("Should expire the cancelled period without grace", async () => {
+        await program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc();
+        await program.methods
+            .setCancelAtPeriodEnd(true)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .signers([user])
+            .rpc();
+        await wait(period + 1000);
+        await expectError(verifyAccess(), "NoActiveSubscription");
+        await program.methods
+            .unsubscribe(false)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, toAccount: null, newDesiredPlan: null})
+            .signers([user])
+            .rpc();
+        if (await status() !== "expired") throw new Error("Unsubscribed subscription is not expired");
+    });
+});
@@ -1,7 +1,9 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
-import { Keypair, PublicKey } from "@solana/web3.js";
+import { Keypair, LAMPORTS_PER_SOL, PublicKey } from "@solana/web3.js";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
 
 
 describe("main_state", () => {
@@ -17,14 +19,16 @@ describe("main_state", () => {
   const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
   console.log(`Provided program id: ${program.programId.toBase58()}`);
   const keypair = anchor.web3.Keypair.generate();
+  const tenantId = new BN(0);
+  const otherTenantId = new BN(Date.now());
 
-  const [pda, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState')], program.programId);
+  const [pda, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
   
   it("The PDA should be initialized!", async () => {
     // Add your test here.
     try {
       const tx = await program.methods
-      .intializeMainState(10)
+      .intializeMainState(tenantId, feeSchedule, configDelay)
       .rpc();
       // show the main state
       const mainState = await program.account.mainState.fetch(pda);
@@ -34,6 +38,21 @@ describe("main_state", () => {
     }
   });
 
+  it("Should initialize another tenant's main state without touching the first one", async () => {
+    const [otherPda, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), otherTenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    await program.methods
+      .intializeMainState(otherTenantId, {...feeSchedule, earlyTerminationBps: 2000}, configDelay)
+      .rpc();
+    const otherMainState = await program.account.mainState.fetch(otherPda);
+    const mainState = await program.account.mainState.fetch(pda);
+    if (!otherMainState.tenantId.eq(otherTenantId) || otherMainState.feeSchedule.earlyTerminationBps !== 2000) {
+      throw new Error("Other tenant's main state was not initialized with given values");
+    }
+    if (!mainState.tenantId.eq(tenantId) || mainState.feeSchedule.earlyTerminationBps !== 1000) {
+      throw new Error("First tenant's main state was changed by other tenant's initialization");
+    }
+  });
+
   it('Should load the main state with correct authority value', async () => {
     const mainState = await program.account.mainState.fetch(pda);
     if( mainState.authority.toBase58() !== provider.wallet.publicKey.toBase58() ) {
@@ -41,75 +60,287 @@ describe("main_state", () => {
     }
   });
 
-  it('Should change the authority and then back to provider (correct signer)', async () => {
-    const tx = await program.methods
-      .updateAuthority(keypair.publicKey)
-      .accounts({
-        mainState: pda
-      })
+  it('Should propose the authority and let only the proposed key accept it', async () => {
+    await program.methods
+      .proposeAuthority(keypair.publicKey)
+      .accounts({ mainState: pda })
       .rpc();
-    const mainState = await program.account.mainState.fetch(pda);
-    if (mainState.authority.toBase58() !== keypair.publicKey.toString()) {
-      throw new Error("Authority is not the same as the provider's pubkey");
+    let mainState = await program.account.mainState.fetch(pda);
+    if (mainState.pendingAuthority?.toBase58() !== keypair.publicKey.toBase58() || mainState.authority.toBase58() !== provider.wallet.publicKey.toBase58()) {
+      throw new Error("Authority should be only proposed until accepted");
+    }
+    let err = null;
+    try {
+      await program.methods
+        .acceptAuthority()
+        .accounts({ mainState: pda })
+        .rpc();
+      err = "Other key than the proposed one was able to accept the authority";
+    } catch (e) {}
+    if (err) throw new Error(err);
+
+    await program.methods
+      .acceptAuthority()
+      .accounts({ mainState: pda, signer: keypair.publicKey })
+      .signers([keypair])
+      .rpc();
+    mainState = await program.account.mainState.fetch(pda);
+    if (mainState.authority.toBase58() !== keypair.publicKey.toString() || mainState.pendingAuthority !== null) {
+      throw new Error("Authority is not the same as the accepted pubkey");
     }
   });
 
-  it('Should change the owner to temp_owner (init_owner signer) and then back to original init_owner (temp_owner signer)', async () => {
-    const tx = await program.methods
-      .updateOwner(keypair.publicKey)
+  it('Should change the owner to temp_owner (init_owner proposes, temp_owner accepts) and then back to original init_owner', async () => {
+    await program.methods
+      .proposeOwner(keypair.publicKey)
       .accounts({mainState: pda})
       .rpc();
+    await program.methods
+      .acceptOwner()
+      .accounts({mainState: pda, signer: keypair.publicKey})
+      .signers([keypair])
+      .rpc();
     const mainState = await program.account.mainState.fetch(pda);
     if (mainState.owner.toBase58() !== keypair.publicKey.toBase58()) {
-      throw new Error("Owner is not the same as the provider's pubkey");
+      throw new Error("Owner is not the same as the accepted pubkey");
     }
 
-    const tx2 = await program.methods
-      .updateOwner(provider.wallet.publicKey)
+    await program.methods
+      .proposeOwner(provider.wallet.publicKey)
       .accounts({mainState: pda, signer: keypair.publicKey})
       .signers([keypair])
       .rpc();
+    await program.methods
+      .acceptOwner()
+      .accounts({mainState: pda})
+      .rpc();
     const mainState2 = await program.account.mainState.fetch(pda);
     if (mainState2.owner.toBase58() !== provider.wallet.publicKey.toBase58()) {
       throw new Error("Owner is not the same as the provider's pubkey");
     }
   });
 
-  it('Should change the owner to himself (correct signer), but other user cant change the owner (unauthorized signer)', async () => {
-    const tx = await program.methods
-      .updateOwner(provider.wallet.publicKey)
+  it('Should cancel the owner proposal, and other user cant propose the owner (unauthorized signer)', async () => {
+    const userAttemptor = await anchor.web3.Keypair.generate();
+    await program.methods
+      .proposeOwner(userAttemptor.publicKey)
+      .accounts({ mainState: pda })
+      .rpc();
+    await program.methods
+      .cancelOwnerProposal()
       .accounts({ mainState: pda })
       .rpc();
+    let err = null;
+    try {
+      await program.methods
+        .acceptOwner()
+        .accounts({ mainState: pda, signer: userAttemptor.publicKey })
+        .signers([userAttemptor])
+        .rpc();
+      err = "Cancelled proposal was accepted";
+    } catch (e) {}
+    if (err) throw new Error(err);
+
+    try {
+      await program.methods
+        .proposeOwner(userAttemptor.publicKey)
+        .accounts({ mainState: pda, signer: userAttemptor.publicKey })
+        .signers([userAttemptor])
+        .rpc();
+      err = "User with no permission was able to propose the owner";
+    } catch (e) {}
+    if (err) throw new Error(err);
     const mainState = await program.account.mainState.fetch(pda);
-    if (mainState.owner.toBase58() !== provider.publicKey.toBase58()) {
+    if (mainState.owner.toBase58() !== provider.publicKey.toBase58() || mainState.pendingOwner !== null) {
       throw new Error("Owner is not the same as the provider's pubkey");
     }
+  });
+
+  it("Should change the authority back to our init_provider", async () => {
+    await program.methods
+      .proposeAuthority(provider.wallet.publicKey)
+      .accounts({ mainState: pda })
+      .rpc();
+    await program.methods
+      .acceptAuthority()
+      .accounts({ mainState: pda })
+      .rpc();
+    const mainState = await program.account.mainState.fetch(pda);
+    if (mainState.authority.toBase58() !== provider.wallet.publicKey.toBase58()) {
+      throw new Error("Authority is not the same as the provider's pubkey");
+    }
+  });
+
+  it("Should require the multisig threshold for fee changes once the multisig is set", async () => {
+    const [otherPda, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), otherTenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const cosigner = anchor.web3.Keypair.generate();
+    const otherCosigner = anchor.web3.Keypair.generate();
+    await program.methods
+      .setMultisig([provider.wallet.publicKey, cosigner.publicKey, otherCosigner.publicKey], 2)
+      .accounts({ mainState: otherPda })
+      .rpc();
 
-    const userAttemptor = await anchor.web3.Keypair.generate();
     let err = null;
     try {
+      await program.methods
+        .updateFees({...feeSchedule, earlyTerminationBps: 3000})
+        .accounts({ mainState: otherPda })
+        .rpc();
+      err = "Owner alone was able to change the fees with the multisig set";
+    } catch (e) {}
+    if (err) throw new Error(err);
 
-      const tx2 = await program.methods
-        .updateOwner(userAttemptor.publicKey)
-        .accounts({ mainState: pda, signer: userAttemptor.publicKey })
-        .signers([userAttemptor])
+    await program.methods
+      .updateFees({...feeSchedule, earlyTerminationBps: 3000})
+      .accounts({ mainState: otherPda })
+      .remainingAccounts([{ pubkey: cosigner.publicKey, isSigner: true, isWritable: false }])
+      .signers([cosigner])
+      .rpc();
+    let mainState = await program.account.mainState.fetch(otherPda);
+    if (mainState.pendingConfig === null || mainState.pendingConfig.feeSchedule.earlyTerminationBps !== 3000) {
+      throw new Error("Fees were not queued with the multisig threshold met");
+    }
+    try {
+      await program.methods
+        .cancelPendingConfig()
+        .accounts({ mainState: otherPda })
         .rpc();
-      err = ("User with no permission was able to change the owner");
+      err = "Owner alone was able to cancel the queued config with the multisig set";
     } catch (e) {
-      const mainState = await program.account.mainState.fetch(pda);
+      if (!e.toString().includes("MultisigThresholdNotMet")) err = `Unexpected error: ${e}`;
     }
     if (err) throw new Error(err);
 
+    await program.methods
+      .setMultisig([], 0)
+      .accounts({ mainState: otherPda })
+      .remainingAccounts([{ pubkey: otherCosigner.publicKey, isSigner: true, isWritable: false }])
+      .signers([otherCosigner])
+      .rpc();
+    mainState = await program.account.mainState.fetch(otherPda);
+    if (mainState.multisig !== null) {
+      throw new Error("Multisig was not removed");
+    }
+  });
 
+  it("Should let only the fee manager in force change the fees", async () => {
+    const [otherPda, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), otherTenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const feeManager = anchor.web3.Keypair.generate();
+    await program.methods
+      .grantRole({ feeManager: {} }, feeManager.publicKey)
+      .accounts({ mainState: otherPda })
+      .rpc();
+    let mainState = await program.account.mainState.fetch(otherPda);
+    if (mainState.pendingConfig.roles.feeManager.toBase58() !== feeManager.publicKey.toBase58() || mainState.roles.feeManager.toBase58() !== provider.wallet.publicKey.toBase58()) {
+      throw new Error("Fee manager should be only queued until the config delay passes");
+    }
+
+    // the granted fee manager takes over only once the queued role is applied
+    let err = null;
+    try {
+      await program.methods
+        .updateFees({...feeSchedule, earlyTerminationBps: 4000})
+        .accounts({ mainState: otherPda, signer: feeManager.publicKey })
+        .signers([feeManager])
+        .rpc();
+      err = "Queued fee manager was able to change the fees";
+    } catch (e) {}
+    if (err) throw new Error(err);
+
+    await program.methods
+      .updateFees({...feeSchedule, earlyTerminationBps: 4000})
+      .accounts({ mainState: otherPda })
+      .rpc();
+    await program.methods
+      .revokeRole({ feeManager: {} })
+      .accounts({ mainState: otherPda })
+      .rpc();
+    mainState = await program.account.mainState.fetch(otherPda);
+    if (mainState.pendingConfig.feeSchedule.earlyTerminationBps !== 4000 || mainState.pendingConfig.roles.feeManager !== null) {
+      throw new Error("Fees and the revoked role were not queued");
+    }
   });
-  it("Should change the authority back to our init_provider", async () => {
+
+  it("Shouldn't accept fees over 100%", async () => {
+    let err = null;
+    try {
+      await program.methods
+        .updateFees({...feeSchedule, withdrawalBps: 10001})
+        .accounts({ mainState: pda })
+        .rpc();
+      err = "Fee schedule with fee over 100% was accepted";
+    } catch (e) {
+      if (!e.toString().includes("InvalidFeeSchedule")) err = `Unexpected error: ${e}`;
+    }
+    if (err) throw new Error(err);
+  });
+
+  it("Should emit ConfigQueued event through the self CPI", async () => {
     const tx = await program.methods
-      .updateAuthority(provider.wallet.publicKey)
+      .updateFees(feeSchedule)
       .accounts({ mainState: pda })
+      .rpc({ commitment: "confirmed" });
+    const txInfo = await provider.connection.getTransaction(tx, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+    // event CPI data is the 8 bytes event instruction tag followed by the event
+    const events = txInfo.meta.innerInstructions
+      .flatMap(inner => inner.instructions)
+      .map(ix => program.coder.events.decode(anchor.utils.bytes.base64.encode(anchor.utils.bytes.bs58.decode(ix.data).subarray(8))))
+      .filter(event => event);
+    const configQueued = events.find(event => event.name.toLowerCase() === "configqueued");
+    if (!configQueued || configQueued.data.pendingConfig.feeSchedule.earlyTerminationBps !== 1000 || configQueued.data.mainState.toBase58() !== pda.toBase58()) {
+      throw new Error("ConfigQueued event was not emitted");
+    }
+  });
+
+  it("Should apply the queued config only after the config delay", async () => {
+    const [otherPda, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), otherTenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const longerDelay = configDelay.muln(2);
+    let err = null;
+    try {
+      await program.methods
+        .intializeMainState(new BN(Date.now()), feeSchedule, new BN(1000 * 60 * 60))
+        .rpc();
+      err = "Main state was initialized with the config delay below the minimum";
+    } catch (e) {
+      if (!e.toString().includes("InvalidConfigDelay")) err = `Unexpected error: ${e}`;
+    }
+    if (err) throw new Error(err);
+    try {
+      await program.methods
+        .setConfigDelay(new BN(1000 * 60 * 60))
+        .accounts({ mainState: otherPda })
+        .rpc();
+      err = "Config delay below the minimum was queued";
+    } catch (e) {
+      if (!e.toString().includes("InvalidConfigDelay")) err = `Unexpected error: ${e}`;
+    }
+    if (err) throw new Error(err);
+    await program.methods
+      .setConfigDelay(longerDelay)
+      .accounts({ mainState: otherPda })
       .rpc();
-    const mainState = await program.account.mainState.fetch(pda);
-    if (mainState.authority.toBase58() !== provider.wallet.publicKey.toBase58()) {
-      throw new Error("Authority is not the same as the provider's pubkey");
+    try {
+      await program.methods
+        .applyPendingConfig()
+        .accounts({ mainState: otherPda })
+        .rpc();
+      err = "Queued config was applied before the config delay";
+    } catch (e) {
+      if (!e.toString().includes("ConfigNotEffective")) err = `Unexpected error: ${e}`;
+    }
+    if (err) throw new Error(err);
+    let mainState = await program.account.mainState.fetch(otherPda);
+    if (!mainState.configDelay.eq(configDelay) || !mainState.pendingConfig.configDelay.eq(longerDelay)) {
+      throw new Error("Config delay should be only queued until the config delay passes");
+    }
+
+    await program.methods
+      .cancelPendingConfig()
+      .accounts({ mainState: otherPda })
+      .rpc();
+    mainState = await program.account.mainState.fetch(otherPda);
+    if (mainState.pendingConfig !== null || !mainState.configDelay.eq(configDelay)) {
+      throw new Error("Pending config was not cancelled");
     }
   });
 
@@ -117,8 +348,8 @@ describe("main_state", () => {
     let err = null;
     try {
       const tx = await program.methods
-      .updateOwner(keypair.publicKey)
-      .accounts({ mainState: pda })
+      .proposeOwner(keypair.publicKey)
+      .accounts({ mainState: pda, signer: keypair.publicKey })
       .signers([keypair])
       .rpc();
       err = "User with no permission was able to change the owner";
@@ -0,0 +1,132 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
+import { LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
+
+
+describe("plans", () => {
+  const provider = anchor.AnchorProvider.env()
+
+  anchor.setProvider(provider);
+
+  const idl = require("../target/idl/w_3_subs_tracker.json") as any;
+  const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+  const tenantId = new BN(Date.now());
+  const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+  // unique plan id, so the tests can be rerun against the same main state
+  const planId = new BN(Date.now());
+  const [planPDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), planId.toArrayLike(Buffer, 'le', 8)], program.programId);
+  const period = new BN(1000 * 60 * 60 * 24 * 7);
+  const fakeOwner = anchor.web3.Keypair.generate();
+  const user = anchor.web3.Keypair.generate();
+
+  it("Should init main state and airdrops", async () => {
+    try {
+      await program.account.mainState.fetch(mainStatePDA);
+    } catch {
+      await program.methods
+        .intializeMainState(tenantId, feeSchedule, configDelay)
+        .rpc({skipPreflight: true});
+    }
+    for (const keypair of [fakeOwner, user]) {
+      const tx = await provider.connection.requestAirdrop(keypair.publicKey, 2 * LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(tx);
+    }
+  });
+
+  it("Should let only the owner create a plan", async () => {
+    let err = null;
+    try {
+      await program.methods
+        .createPlan(planId, "weekly", new BN(LAMPORTS_PER_SOL * 0.1), period, 1)
+        .accounts({mainState: mainStatePDA, signer: fakeOwner.publicKey})
+        .signers([fakeOwner])
+        .rpc({skipPreflight: true});
+      err = new Error("Should not have let fake owner create a plan");
+    } catch {}
+    if (err) throw err;
+
+    await program.methods
+      .createPlan(planId, "weekly", new BN(LAMPORTS_PER_SOL * 0.1), period, 1)
+      .accounts({mainState: mainStatePDA})
+      .rpc({skipPreflight: true});
+    const plan = await program.account.plan.fetch(planPDA);
+    if (plan.name !== "weekly" || plan.priceLamports.toNumber() !== LAMPORTS_PER_SOL * 0.1 || !plan.period.eq(period) || plan.tier !== 1 || !plan.active) {
+      throw new Error("Plan was not created with given values");
+    }
+    if (plan.mainState.toBase58() !== mainStatePDA.toBase58()) throw new Error("Plan doesn't belong to the main state");
+  });
+
+  it("Shouldn't create a plan with too long name or empty period", async () => {
+    const otherPlanId = planId.addn(1);
+    let err = null;
+    try {
+      await program.methods
+        .createPlan(otherPlanId, "x".repeat(33), new BN(LAMPORTS_PER_SOL * 0.1), period, 1)
+        .accounts({mainState: mainStatePDA})
+        .rpc({skipPreflight: true});
+      err = new Error("Should not have created a plan with too long name");
+    } catch {}
+    if (err) throw err;
+    try {
+      await program.methods
+        .createPlan(otherPlanId, "monthly", new BN(LAMPORTS_PER_SOL * 0.1), new BN(0), 1)
+        .accounts({mainState: mainStatePDA})
+        .rpc({skipPreflight: true});
+      err = new Error("Should not have created a plan with empty period");
+    } catch {}
+    if (err) throw err;
+  });
+
+  it("Should let only the owner update the plan, keeping unspecified values", async () => {
+    let err = null;
+    try {
+      await program.methods
+        .updatePlan(null, new BN(0), null, null)
+        .accounts({plan: planPDA, mainState: mainStatePDA, signer: fakeOwner.publicKey})
+        .signers([fakeOwner])
+        .rpc({skipPreflight: true});
+      err = new Error("Should not have let fake owner update the plan");
+    } catch {}
+    if (err) throw err;
+
+    await program.methods
+      .updatePlan(null, new BN(LAMPORTS_PER_SOL * 0.2), null, 2)
+      .accounts({plan: planPDA, mainState: mainStatePDA})
+      .rpc({skipPreflight: true});
+    const plan = await program.account.plan.fetch(planPDA);
+    if (plan.name !== "weekly" || plan.priceLamports.toNumber() !== LAMPORTS_PER_SOL * 0.2 || !plan.period.eq(period) || plan.tier !== 2) {
+      throw new Error("Plan was not updated correctly");
+    }
+  });
+
+  it("Should retire the plan so it can't be chosen by users anymore", async () => {
+    await program.methods
+      .retirePlan()
+      .accounts({plan: planPDA, mainState: mainStatePDA})
+      .rpc({skipPreflight: true});
+    const plan = await program.account.plan.fetch(planPDA);
+    if (plan.active) throw new Error("Plan should be retired");
+
+    let err = null;
+    try {
+      await program.methods
+        .createSubscription(new BN(LAMPORTS_PER_SOL * 0.5), null)
+        .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+        .signers([user])
+        .rpc({skipPreflight: true});
+      err = new Error("Should not have let user subscribe to a retired plan");
+    } catch {}
+    if (err) throw err;
+    try {
+      await program.methods
+        .updatePlan(null, new BN(LAMPORTS_PER_SOL * 0.3), null, null)
+        .accounts({plan: planPDA, mainState: mainStatePDA})
+        .rpc({skipPreflight: true});
+      err = new Error("Should not have let owner update a retired plan");
+    } catch {}
+    if (err) throw err;
+  });
+});
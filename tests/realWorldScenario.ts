@@ -4,6 +4,7 @@ import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
 import { Keypair, LAMPORTS_PER_SOL, PublicKey } from "@solana/web3.js";
 import { BN } from "bn.js";
 import { use } from "chai";
+import { configDelay, feeSchedule } from "./config";
 
 
 
@@ -21,8 +22,13 @@ describe("real_world_scenairo", async () => {
 
     const idl = require("../target/idl/w_3_subs_tracker.json") as any;
     const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    const tenantId = new BN(Date.now());
     const usersKeyPairs = [anchor.web3.Keypair.generate(),  anchor.web3.Keypair.generate(), anchor.web3.Keypair.generate()];
-    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState')], program.programId);
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const basicPlanId = new BN(1);
+    const premiumPlanId = new BN(2);
+    const [basicPlanPDA, ___] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), basicPlanId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const [premiumPlanPDA, ____] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), premiumPlanId.toArrayLike(Buffer, 'le', 8)], program.programId);
     const subsPdas = [];
     // INIT OF USERS, MAIN STATE, AIRDROPS
     it("Should init", async () => {
@@ -32,16 +38,26 @@ describe("real_world_scenairo", async () => {
         } catch {
             // if mainState does not exist, create it
             const tx = await program.methods
-                .intializeMainState(10)
+                .intializeMainState(tenantId, feeSchedule, configDelay)
                 .rpc({skipPreflight: true});
         }
+        for (const [planPDA, planId, name, price, tier] of [[basicPlanPDA, basicPlanId, "basic", 0.3, 1], [premiumPlanPDA, premiumPlanId, "premium", 0.4, 2]] as const) {
+            try {
+                await program.account.plan.fetch(planPDA);
+            } catch {
+                await program.methods
+                    .createPlan(planId, name, new BN(LAMPORTS_PER_SOL * price), new BN(1000 * 60 * 60 * 24 * 30), tier)
+                    .accounts({mainState: mainStatePDA})
+                    .rpc({skipPreflight: true});
+            }
+        }
         for(let i = 0; i < usersKeyPairs.length; i++) {
             const userKeyPair = usersKeyPairs[i];
             const [pda, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), userKeyPair.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId);
             subsPdas.push(pda);
         }
     //    const tx = await program.methods
-    //         .intializeMainState(10)
+    //         .intializeMainState(tenantId, feeSchedule, configDelay)
     //         .rpc();
        const mainState = await program.account.mainState.fetch(mainStatePDA);
        console.log('Main state initialized: ', JSON.stringify(mainState));
@@ -62,10 +78,9 @@ describe("real_world_scenairo", async () => {
         const userKeyPair = usersKeyPairs[0];
         const pda = subsPdas[0];
         const userBalanceAtStart = await provider.connection.getBalance(userKeyPair.publicKey);
-        const d = {basic: {}};
         const tx = await program.methods
-            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.25), d)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.25), null)
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: basicPlanPDA})
             .signers([userKeyPair])
             .rpc();
         let balance = await provider.connection.getBalance(pda);
@@ -73,11 +88,11 @@ describe("real_world_scenairo", async () => {
         if (balance < LAMPORTS_PER_SOL * 0.25)  throw new Error("Balance is not correct");
         let subsInfo = await program.account.subscription.fetch(pda);
         console.log('__AFTER INIT SUBCRIPTION');
-        console.log(`User has initialized a subscription with ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL with desired subs type ${JSON.stringify(subsInfo.subscriptionStatusWritable.desiredSubscriptionType)}, this all happened at UTC: ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp ? subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber() : 0).toUTCString()}`);
+        console.log(`User has initialized a subscription with ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL with desired subs type ${subsInfo.subscriptionStatusWritable.desiredPlan.toBase58()}, this all happened at UTC: ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp ? subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber() : 0).toUTCString()}`);
         console.log(`BE will check the status of the subscription account, but it requires atleast 0.3 SOL`)
-        console.log(`His subs account now have ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`His subs account now have ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User is about to deposit 0.1 additional SOL to the subscription account \n`);
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() < LAMPORTS_PER_SOL * 0.25 || subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() > LAMPORTS_PER_SOL * 0.251) throw new Error("Balance of credits is not correct");
+        if (subsInfo.ledger.credits.toNumber() < LAMPORTS_PER_SOL * 0.25 || subsInfo.ledger.credits.toNumber() > LAMPORTS_PER_SOL * 0.251) throw new Error("Balance of credits is not correct");
 
         const tx2 = await program.methods
             .fundSubscription(new BN(LAMPORTS_PER_SOL * 0.1))
@@ -88,33 +103,33 @@ describe("real_world_scenairo", async () => {
         subsInfo = await program.account.subscription.fetch(pda);
         console.log("__AFTER DEPOSIT")
         console.log(`User has deposited 0.1 SOL to the subscription account, the account now PDA has ${balance / LAMPORTS_PER_SOL} SOL`);
-        console.log(`User has deposited 0.1 SOL to the subscription account, the account now has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL`);
-        console.log(`His subs account now have ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`User has deposited 0.1 SOL to the subscription account, the account now has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL`);
+        console.log(`His subs account now have ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         const validTill = Date.now() + 1000;
         console.log('It will be valid till', new Date(validTill).toUTCString());   
         console.log(`BE will check the status of the subscription account, everything is fine now it will set date and subs type \n`);
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() < LAMPORTS_PER_SOL * 0.35 || subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() > LAMPORTS_PER_SOL * 0.351) throw new Error("Balance of credits is not correct");
+        if (subsInfo.ledger.credits.toNumber() < LAMPORTS_PER_SOL * 0.35 || subsInfo.ledger.credits.toNumber() > LAMPORTS_PER_SOL * 0.351) throw new Error("Balance of credits is not correct");
         
         const tx3 = await program.methods
-            .setSubscriptionInfo(new BN(validTill), new BN(LAMPORTS_PER_SOL * 0.3), d)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey})
+            .setSubscriptionInfo(new BN(validTill), new BN(LAMPORTS_PER_SOL * 0.3))
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, plan: basicPlanPDA})
             .rpc();
         subsInfo = await program.account.subscription.fetch(pda);   
         console.log('__AFTER SET SUBSCRIPTION INFO')
-        console.log(`User has been set up with subscription type to ${JSON.stringify(subsInfo.authorityWritable.currentAccountType)} and valid from ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber())} the valid till ${new Date(subsInfo.authorityWritable.validTill?.toNumber())}`);
-        console.log(`His subs account now have ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`User has been set up with subscription type to ${subsInfo.authorityWritable.currentPlan.toBase58()} and valid from ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber())} the valid till ${new Date(subsInfo.authorityWritable.validTill?.toNumber())}`);
+        console.log(`His subs account now have ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User want to change his subscription type to premium, he need to unsubscribe first \n`);
         let userBalance = await provider.connection.getBalance(userKeyPair.publicKey); 
         console.log(`User balance is ${userBalance / LAMPORTS_PER_SOL} SOL`);
         console.log('After unsubscribe time: ' + new Date().toUTCString() + '\n') ;
         if (subsInfo.authorityWritable.validTill.toNumber() < Date.now()) throw new Error("Valid till date should be valid at this point");
         await wait(2000);
-        if (subsInfo.authorityWritable.usedLamports.toNumber() < LAMPORTS_PER_SOL * 0.3 || subsInfo.authorityWritable.usedLamports.toNumber() > LAMPORTS_PER_SOL * 0.31) throw new Error("Balance of debits is not correct");   
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() < LAMPORTS_PER_SOL * 0.05 || subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() > LAMPORTS_PER_SOL * 0.051) throw new Error("Balance of credits is not correct");
+        if (subsInfo.ledger.locked.toNumber() < LAMPORTS_PER_SOL * 0.3 || subsInfo.ledger.locked.toNumber() > LAMPORTS_PER_SOL * 0.31) throw new Error("Balance of debits is not correct");   
+        if (subsInfo.ledger.credits.toNumber() < LAMPORTS_PER_SOL * 0.05 || subsInfo.ledger.credits.toNumber() > LAMPORTS_PER_SOL * 0.051) throw new Error("Balance of credits is not correct");
         if (subsInfo.authorityWritable.validTill.toNumber() > Date.now()) throw new Error("Valid till date shoul NOT be valid at this point");
         const tx4 = await program.methods
-            .unsubscribe(true, d)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, mainStateOwner: provider.wallet.publicKey, toAccount: userKeyPair.publicKey})
+            .unsubscribe(true)
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, toAccount: userKeyPair.publicKey, newDesiredPlan: basicPlanPDA})
             .signers([userKeyPair])
             .rpc({skipPreflight: true});
         console.log('__AFTER UNSUBSCRIBE')
@@ -122,22 +137,58 @@ describe("real_world_scenairo", async () => {
         subsInfo = await program.account.subscription.fetch(pda);
         userBalance = await provider.connection.getBalance(userKeyPair.publicKey);
         console.log(`User has a subscription account`);
-        console.log(`His subs account now have ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`His subs account now have ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${userBalance / LAMPORTS_PER_SOL} SOL`);
-        if(Object.keys(subsInfo.subscriptionStatusWritable.desiredSubscriptionType)[0] !== 'basic') throw new Error("Desired subscription type is not correct"); 
+        if(subsInfo.subscriptionStatusWritable.desiredPlan.toBase58() !== basicPlanPDA.toBase58()) throw new Error("Desired subscription type is not correct"); 
         
         
     });
 
+    it("Should let user0: close the emptied subscription account -> get the rent back -> create a new subscription later", async () => {
+        const userKeyPair = usersKeyPairs[0];
+        const pda = subsPdas[0];
+        const userBalanceBefore = await provider.connection.getBalance(userKeyPair.publicKey);
+        const pdaBalance = await provider.connection.getBalance(pda);
+        await program.methods
+            .closeSubscription()
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+            .signers([userKeyPair])
+            .rpc();
+        if (await provider.connection.getAccountInfo(pda) !== null) throw new Error("Subscription account was not closed");
+        const userBalanceAfter = await provider.connection.getBalance(userKeyPair.publicKey);
+        console.log(`User got back ${(userBalanceAfter - userBalanceBefore) / LAMPORTS_PER_SOL} SOL of the ${pdaBalance / LAMPORTS_PER_SOL} SOL held by the closed PDA`);
+        if (userBalanceAfter <= userBalanceBefore) throw new Error("Rent was not returned to the user");
+
+        await program.methods
+            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.1), null)
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: basicPlanPDA})
+            .signers([userKeyPair])
+            .rpc();
+        const subsInfo = await program.account.subscription.fetch(pda);
+        if (!subsInfo.ledger.credits.eq(new BN(LAMPORTS_PER_SOL * 0.1)) || !subsInfo.ledger.earned.isZero()) throw new Error("Recreated subscription doesn't start with a fresh ledger");
+
+        let err = null;
+        try {
+            await program.methods
+                .closeSubscription()
+                .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                .signers([userKeyPair])
+                .rpc();
+            err = "Subscription with credits was closed";
+        } catch (e) {
+            if (!e.toString().includes("SubscriptionNotEmpty")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+    });
+
     // USER1
     it("Should let user: creates subs acc -> gets verified by BE and setted up -> after some type account becomes invalid: (valid_till < now) -> funds more to keep account active -> get reverified by BE and setted up -> BE withdrawal during valid period (allowance of temp withdrawal) -> BE withdrawal after valid period (allowance of full debit withdrawal)", async () => {
         let userKeyPair = usersKeyPairs[1];
         const pda = subsPdas[1];
-        const d = {basic: {}};
 
         const tx = await program.methods
-            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.1), d)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.1), null)
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: basicPlanPDA})
             .signers([userKeyPair])
             .rpc();
 
@@ -145,25 +196,25 @@ describe("real_world_scenairo", async () => {
         let subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER INIT SUBCRIPTION`)
         console.log(`User has created a subscription account with ${balance / LAMPORTS_PER_SOL} SOL`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL \n`);
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() !== LAMPORTS_PER_SOL * 0.1 ) throw new Error("Balance of credits is not correct");
+        if (subsInfo.ledger.credits.toNumber() !== LAMPORTS_PER_SOL * 0.1 ) throw new Error("Balance of credits is not correct");
         if (balance < LAMPORTS_PER_SOL * 0.1)  throw new Error("Balance is not correct");
 
         const tx1 = await program.methods
-            .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.08), d)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey})
+            .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.08))
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, plan: basicPlanPDA})
             .rpc();
 
         console.log(`__AFTER SET SUBSCRIPTION INFO`)
         subsInfo = await program.account.subscription.fetch(pda);
         if (subsInfo.authorityWritable.validTill.toNumber() < Date.now()) throw new Error("Valid till date should be valid at this point");
         await wait(2000);
-        console.log(`User has been set up with subscription type to ${JSON.stringify(subsInfo.authorityWritable.currentAccountType)} and valid from ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber())} the valid till ${new Date(subsInfo.authorityWritable.validTill?.toNumber())}`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`User has been set up with subscription type to ${subsInfo.authorityWritable.currentPlan.toBase58()} and valid from ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber())} the valid till ${new Date(subsInfo.authorityWritable.validTill?.toNumber())}`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`In next step user will be using his subcription account till valid till date \n`)
-        if (subsInfo.authorityWritable.usedLamports.toNumber() !== LAMPORTS_PER_SOL * 0.08 ) throw new Error("Balance of debits is not correct");
+        if (subsInfo.ledger.locked.toNumber() !== LAMPORTS_PER_SOL * 0.08 ) throw new Error("Balance of debits is not correct");
         if (subsInfo.authorityWritable.validTill.toNumber() > Date.now()) throw new Error("Valid till date should NOT be valid at this point");
 
 
@@ -181,58 +232,60 @@ describe("real_world_scenairo", async () => {
         subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER DEPOSIT`)
         console.log(`User has deposited 0.1 SOL to the subscription account, the account now PDA has ${balance / LAMPORTS_PER_SOL} SOL`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`User has funded his account, now he wants to get reverified by BE by calling some API \n`);
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() !== LAMPORTS_PER_SOL * 0.12 ) throw new Error("Balance of credits is not correct");
-        if (subsInfo.authorityWritable.usedLamports.toNumber() !== LAMPORTS_PER_SOL * 0.08 ) throw new Error("Balance of credits is not correct");
+        if (subsInfo.ledger.credits.toNumber() !== LAMPORTS_PER_SOL * 0.12 ) throw new Error("Balance of credits is not correct");
+        if (subsInfo.ledger.locked.toNumber() !== LAMPORTS_PER_SOL * 0.08 ) throw new Error("Balance of credits is not correct");
 
         const tx3 = await program.methods
-            .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.1), null)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey})
+            .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.1))
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, plan: null})
             .rpc();
         subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER REVERIFY`)
-        console.log(`User has been set up with subscription type to ${JSON.stringify(subsInfo.authorityWritable.currentAccountType)} and valid from ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber())} the valid till ${new Date(subsInfo.authorityWritable.validTill?.toNumber())}`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`User has been set up with subscription type to ${subsInfo.authorityWritable.currentPlan.toBase58()} and valid from ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber())} the valid till ${new Date(subsInfo.authorityWritable.validTill?.toNumber())}`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL \n`);
         if (subsInfo.authorityWritable.validTill.toNumber() < Date.now()) throw new Error("Valid till date should be valid at this point");
-        if (subsInfo.authorityWritable.usedLamports.toNumber() !== LAMPORTS_PER_SOL * 0.1 ) throw new Error("Balance of debits is not correct");
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() !== LAMPORTS_PER_SOL * 0.02 ) throw new Error("Balance of credits is not correct");
+        if (subsInfo.ledger.locked.toNumber() !== LAMPORTS_PER_SOL * 0.1 ) throw new Error("Balance of debits is not correct");
+        if (subsInfo.ledger.credits.toNumber() !== LAMPORTS_PER_SOL * 0.02 ) throw new Error("Balance of credits is not correct");
 
         // authority will now withdraw the funds from the account
         console.log(`Authority is about to withdraw all SOL from the subscription account, his current balance is ${await provider.connection.getBalance(provider.wallet.publicKey) / LAMPORTS_PER_SOL} SOL \n`)
         const beforeWithdrawalProviderBalance = await provider.connection.getBalance(provider.wallet.publicKey);
         const tx4 = await program.methods
             .withdraw(null)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, toAccount: provider.wallet.publicKey})
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, treasurer: provider.wallet.publicKey})
             .rpc();
         subsInfo = await program.account.subscription.fetch(pda);
         const afterWithdrawalProviderBalance = await provider.connection.getBalance(provider.wallet.publicKey);
         console.log(`__AFTER WITHDRAW`)
         console.log(`Authority has withdrawn all SOL from the subscription account, the account now PDA has ${await provider.connection.getBalance(pda) / LAMPORTS_PER_SOL} SOL`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`Authority balance is ${await provider.connection.getBalance(provider.wallet.publicKey) / LAMPORTS_PER_SOL} SOL \n`);
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() !== 0.02 * LAMPORTS_PER_SOL ) throw new Error("Balance of credits is not correct");
-        if (subsInfo.authorityWritable.usedLamports.toNumber() !== 0.1 * LAMPORTS_PER_SOL ) throw new Error("Balance of debits is not correct");
+        if (subsInfo.ledger.credits.toNumber() !== 0.02 * LAMPORTS_PER_SOL ) throw new Error("Balance of credits is not correct");
+        if (subsInfo.ledger.locked.toNumber() !== 0.1 * LAMPORTS_PER_SOL ) throw new Error("Balance of debits is not correct");
         if (await provider.connection.getBalance(pda) > LAMPORTS_PER_SOL * 0.13 || await provider.connection.getBalance(pda) < LAMPORTS_PER_SOL * 0.11 ) throw new Error("PDA inner balance is not correct, should hold only rentable amount");
         // console.log((beforeWithdrawalProviderBalance - afterWithdrawalProviderBalance) / LAMPORTS_PER_SOL)
         await wait(2000);
         
         const tx5 = await program.methods
             .withdraw(null)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, toAccount: provider.wallet.publicKey})
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, treasurer: provider.wallet.publicKey})
             .rpc();
         subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER SECOND WITHDRAW`)
         console.log(`Authority has withdrawn all SOL from the subscription account, the account now PDA has ${await provider.connection.getBalance(pda) / LAMPORTS_PER_SOL} SOL`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`Authority balance is ${await provider.connection.getBalance(provider.wallet.publicKey) / LAMPORTS_PER_SOL} SOL \n`);
         if (subsInfo.authorityWritable.validTill.toNumber() > Date.now()) throw new Error("Valid till date should NOT be valid at this point");
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() !== 0.02 * LAMPORTS_PER_SOL ) throw new Error("Balance of credits is not correct");
-        if (subsInfo.authorityWritable.usedLamports.toNumber() !== 0.1 * LAMPORTS_PER_SOL ) throw new Error("Balance of debits is not correct");
+        if (subsInfo.ledger.credits.toNumber() !== 0.02 * LAMPORTS_PER_SOL ) throw new Error("Balance of credits is not correct");
+        // debits of the expired period are settled as earned and withdrawn
+        if (subsInfo.ledger.locked.toNumber() !== 0 || subsInfo.ledger.earned.toNumber() !== 0) throw new Error("Balance of debits is not correct");
+        if (subsInfo.ledger.withdrawn.toNumber() !== 0.18 * LAMPORTS_PER_SOL ) throw new Error("Withdrawn funds are not correct");
         if (await provider.connection.getBalance(pda) > LAMPORTS_PER_SOL * 0.03 || await provider.connection.getBalance(pda) < LAMPORTS_PER_SOL * 0.01 ) throw new Error("PDA inner balance is not correct, should hold only rentable amount");
 
     });
@@ -242,38 +295,36 @@ describe("real_world_scenairo", async () => {
     it("Should let user: creates sub acc -> verified by BE as basic -> unsubscribes without withdrawal ->  changes his desired type to premium -> funds more to be eligible for premium -> gets reverified by BE as premium-> BE withdraw -> unsubcribes with total withdrawal ", async () => {
         const userKeyPair = usersKeyPairs[2];
         const pda = subsPdas[2];
-        const d = {basic: {}};
-        const p = {premium: {}};
 
         const tx = await program.methods
-            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.4), d)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.4), null)
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: basicPlanPDA})
             .signers([userKeyPair])
             .rpc();
         let balance = await provider.connection.getBalance(pda);
         let subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER INIT SUBCRIPTION`)
         console.log(`User has created a subscription account with ${balance / LAMPORTS_PER_SOL} SOL`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL \n`);
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() !== LAMPORTS_PER_SOL * 0.4 ) throw new Error("Balance of credits is not correct");
+        if (subsInfo.ledger.credits.toNumber() !== LAMPORTS_PER_SOL * 0.4 ) throw new Error("Balance of credits is not correct");
         if (balance < LAMPORTS_PER_SOL * 0.4 || balance > 0.41 * LAMPORTS_PER_SOL)  throw new Error("Balance is not correct");
 
         const tx1 = await program.methods
-            .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3), d)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey})
+            .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3))
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, plan: basicPlanPDA})
             .rpc();
         subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER SET SUBSCRIPTION INFO`)
-        console.log(`User has been set up with subscription type to ${JSON.stringify(subsInfo.authorityWritable.currentAccountType)} and valid from ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber())} the valid till ${new Date(subsInfo.authorityWritable.validTill?.toNumber())}`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`User has been set up with subscription type to ${subsInfo.authorityWritable.currentPlan.toBase58()} and valid from ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber())} the valid till ${new Date(subsInfo.authorityWritable.validTill?.toNumber())}`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`In next step user will be using his subcription till 50% of valid till but decides to go to premium instead \n`)
         await wait(500);
 
         const tx2 = await program.methods
-            .unsubscribe(false, null)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, mainStateOwner: provider.wallet.publicKey, toAccount: null})
+            .unsubscribe(false)
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, toAccount: null, newDesiredPlan: null})
             .signers([userKeyPair])
             .rpc({skipPreflight: true});
 
@@ -281,26 +332,26 @@ describe("real_world_scenairo", async () => {
         console.log(`__AFTER UNSUBSCRIBE`)
         console.log(`Tx: ${tx2}`);
         console.log(`User has unsubscribed from the subscription account, the account now PDA has ${await provider.connection.getBalance(pda) / LAMPORTS_PER_SOL} SOL`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`User has unsubscribed from the account, now he wants to create a new account with premium type \n`);
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() <= 0.07 * LAMPORTS_PER_SOL ||  subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() >= 0.35 * LAMPORTS_PER_SOL) throw new Error("Balance of credits is not correct");
-        if(Object.keys(subsInfo.subscriptionStatusWritable.desiredSubscriptionType)[0] !== 'free') throw new Error("Desired subscription type is not correct"); 
+        if (subsInfo.ledger.credits.toNumber() <= 0.07 * LAMPORTS_PER_SOL ||  subsInfo.ledger.credits.toNumber() >= 0.35 * LAMPORTS_PER_SOL) throw new Error("Balance of credits is not correct");
+        if(!subsInfo.subscriptionStatusWritable.desiredPlan.equals(PublicKey.default)) throw new Error("Desired subscription type is not correct"); 
         const tx3 = await program.methods
-            .changeDesiredSubscriptionType(p)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+            .changeDesiredSubscriptionType()
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
             .signers([userKeyPair])
             .rpc();
 
         subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER CHANGE DESIRED SUBSCRIPTION TYPE`)
-        console.log(`User has changed his subscription type to ${JSON.stringify(subsInfo.subscriptionStatusWritable.desiredSubscriptionType)}`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`User has changed his subscription type to ${subsInfo.subscriptionStatusWritable.desiredPlan.toBase58()}`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`User has changed his subscription type to premium, now he wants to fund the account \n`);
-        if(Object.keys(subsInfo.subscriptionStatusWritable.desiredSubscriptionType)[0] !== 'premium') throw new Error("Desired subscription type is not correct"); 
-        const pdaCreditsAfterUnsubscribe = subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber();
-        if (pdaCreditsAfterUnsubscribe <= 0.05 * LAMPORTS_PER_SOL ||  subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() >= 0.35 * LAMPORTS_PER_SOL) throw new Error("Balance of credits is not correct");
+        if(subsInfo.subscriptionStatusWritable.desiredPlan.toBase58() !== premiumPlanPDA.toBase58()) throw new Error("Desired subscription type is not correct"); 
+        const pdaCreditsAfterUnsubscribe = subsInfo.ledger.credits.toNumber();
+        if (pdaCreditsAfterUnsubscribe <= 0.05 * LAMPORTS_PER_SOL ||  subsInfo.ledger.credits.toNumber() >= 0.35 * LAMPORTS_PER_SOL) throw new Error("Balance of credits is not correct");
         
         const tx4 = await program.methods
             .fundSubscription(new BN(LAMPORTS_PER_SOL * 0.2))
@@ -310,21 +361,21 @@ describe("real_world_scenairo", async () => {
         subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER FUND SUBSCRIPTION`)
         console.log(`User has funded his account, the account now PDA has ${await provider.connection.getBalance(pda) / LAMPORTS_PER_SOL} SOL`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`User has funded his account, now he wants to get reverified by BE by calling some API \n`);
-        if (subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() !== pdaCreditsAfterUnsubscribe + LAMPORTS_PER_SOL * 0.2 ) throw new Error("Balance of credits is not correct");
-        if (subsInfo.authorityWritable.usedLamports.toNumber() !== LAMPORTS_PER_SOL * 0 ) throw new Error("Balance of debits is not correct");
+        if (subsInfo.ledger.credits.toNumber() !== pdaCreditsAfterUnsubscribe + LAMPORTS_PER_SOL * 0.2 ) throw new Error("Balance of credits is not correct");
+        if (subsInfo.ledger.locked.toNumber() !== LAMPORTS_PER_SOL * 0 ) throw new Error("Balance of debits is not correct");
         
 
         const tx5 = await program.methods
-            .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.4), p)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey})
+            .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.4))
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, plan: premiumPlanPDA})
             .rpc();
         subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER REVERIFY`)
-        console.log(`User has been set up with subscription type to ${JSON.stringify(subsInfo.authorityWritable.currentAccountType)} and valid from ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber())} the valid till ${new Date(subsInfo.authorityWritable.validTill?.toNumber())}`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`User has been set up with subscription type to ${subsInfo.authorityWritable.currentPlan.toBase58()} and valid from ${new Date(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp.toNumber())} the valid till ${new Date(subsInfo.authorityWritable.validTill?.toNumber())}`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`User has been reverified by BE, now be will withdraw all funds it can \n`);
         if (subsInfo.authorityWritable.validTill.toNumber() < Date.now()) throw new Error("Valid till date should be valid at this point");
@@ -333,31 +384,31 @@ describe("real_world_scenairo", async () => {
         await wait(250);
         const tx6 = await program.methods
             .withdraw(null)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, toAccount: provider.wallet.publicKey})
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, treasurer: provider.wallet.publicKey})
             .rpc();
         subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER WITHDRAW`)
         console.log(`Authority has withdrawn all SOL from the subscription account, the account now PDA has ${await provider.connection.getBalance(pda) / LAMPORTS_PER_SOL} SOL`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`Authority balance is ${await provider.connection.getBalance(provider.wallet.publicKey) / LAMPORTS_PER_SOL} SOL \n`);
-        if (subsInfo.authorityWritable.usedLamports.toNumber() !== 0.4 * LAMPORTS_PER_SOL ) throw new Error("Balance of debits is not correct");
+        if (subsInfo.ledger.locked.toNumber() !== 0.4 * LAMPORTS_PER_SOL ) throw new Error("Balance of debits is not correct");
 
         // user usnubcsribes from given account causing given pda to be empty
         const providedBalanceBefore = await provider.connection.getBalance(provider.publicKey);
         const tx7 = await program.methods
-            .unsubscribe(true, null)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, mainStateOwner: provider.wallet.publicKey, toAccount: userKeyPair.publicKey})
+            .unsubscribe(true)
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, toAccount: userKeyPair.publicKey, newDesiredPlan: null})
             .signers([userKeyPair])
             .rpc({skipPreflight: true});
         subsInfo = await program.account.subscription.fetch(pda);
         console.log(`__AFTER UNSUBSCRIBE`)
         console.log(`User balance is ${await provider.connection.getBalance(userKeyPair.publicKey) / LAMPORTS_PER_SOL} SOL`);
         console.log(`Authority balance was: ${providedBalanceBefore / LAMPORTS_PER_SOL} SOL and now is: ${await provider.connection.getBalance(provider.wallet.publicKey) / LAMPORTS_PER_SOL} SOL`);
-        console.log(`In account info, user has ${subsInfo.subscriptionStatusWritable.afterVerifyCreditLamports.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.authorityWritable.usedLamports.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
+        console.log(`In account info, user has ${subsInfo.ledger.credits.toNumber() / LAMPORTS_PER_SOL} SOL as credits and ${subsInfo.ledger.locked.toNumber() / LAMPORTS_PER_SOL} SOL as debits`);
         console.log(`Real balance of PDA: ${await provider.connection.getBalance(pda) / LAMPORTS_PER_SOL} SOL`);
         console.log(`User has unsubscribed from the account, now he wants to create a new account with premium type \n`);
-        if(Object.keys(subsInfo.subscriptionStatusWritable.desiredSubscriptionType)[0] !== 'free') throw new Error("Desired subscription type is not correct"); 
+        if(!subsInfo.subscriptionStatusWritable.desiredPlan.equals(PublicKey.default)) throw new Error("Desired subscription type is not correct"); 
     });
 
     
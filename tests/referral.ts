@@ -0,0 +1,201 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
+import { LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
+
+
+describe("referral", () => {
+    async function wait(ms: number) {
+        return new Promise(resolve => {
+            setTimeout(resolve, ms);
+        });
+    }
+    const provider = anchor.AnchorProvider.env()
+
+    anchor.setProvider(provider);
+
+    const idl = require("../target/idl/w_3_subs_tracker.json") as any;
+    const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    // own tenant, so the referral share doesn't affect the other tests
+    const tenantId = new BN(Date.now());
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const planId = new BN(1);
+    const [planPDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), planId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const [treasuryPDA, ___] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('treasury'), mainStatePDA.toBuffer()], program.programId);
+    const price = new BN(LAMPORTS_PER_SOL * 0.1);
+    const user = anchor.web3.Keypair.generate();
+    const referrer = anchor.web3.Keypair.generate();
+    const [referrerBalancePDA, ____] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('referrer'), mainStatePDA.toBuffer(), referrer.publicKey.toBuffer()], program.programId);
+
+    it("Should init main state with 20% referral share and short plan", async () => {
+        await program.methods
+            .intializeMainState(tenantId, feeSchedule, configDelay)
+            .rpc();
+        await program.methods
+            .createPlan(planId, "short", price, new BN(2000), 1)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        let err = null;
+        try {
+            await program.methods
+                .setReferralBps(10001)
+                .accounts({mainState: mainStatePDA})
+                .rpc();
+            err = "Referral share above 100% was set";
+        } catch (e) {
+            if (!e.toString().includes("InvalidFeeSchedule")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+        await program.methods
+            .setReferralBps(2000)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        for (const keypair of [user, referrer]) {
+            const tx = await provider.connection.requestAirdrop(keypair.publicKey, LAMPORTS_PER_SOL);
+            await provider.connection.confirmTransaction(tx);
+        }
+    });
+
+    it("Shouldn't let user refer own subscription", async () => {
+        const [userBalancePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('referrer'), mainStatePDA.toBuffer(), user.publicKey.toBuffer()], program.programId);
+        let err = null;
+        try {
+            await program.methods
+                .createSubscription(price, user.publicKey)
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, referrerBalance: userBalancePDA})
+                .signers([user])
+                .rpc();
+            err = "User referred own subscription";
+        } catch (e) {
+            if (!e.toString().includes("InvalidReferrer")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+    });
+
+    it("Should store the referrer and create its balance", async () => {
+        // lamports sent to the balance address beforehand don't block creating it
+        const tx = new anchor.web3.Transaction().add(anchor.web3.SystemProgram.transfer({fromPubkey: provider.wallet.publicKey, toPubkey: referrerBalancePDA, lamports: 1_000_000}));
+        await provider.sendAndConfirm(tx);
+        await program.methods
+            .createSubscription(price, referrer.publicKey)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, referrerBalance: referrerBalancePDA})
+            .signers([user])
+            .rpc();
+        const [subsPDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId);
+        const subscription = await program.account.subscription.fetch(subsPDA);
+        if (subscription.imutableInitialized.referrer.toBase58() !== referrer.publicKey.toBase58()) throw new Error("Referrer is not stored");
+        const balance = await program.account.referrerBalance.fetch(referrerBalancePDA);
+        if (!balance.accrued.eqn(0) || balance.referrer.toBase58() !== referrer.publicKey.toBase58()) throw new Error("Referrer balance is not created");
+    });
+
+    it("Should accrue the referral share on withdraw", async () => {
+        await program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc();
+        await wait(3000);
+        let err = null;
+        try {
+            await program.methods
+                .withdraw(null)
+                .accounts({mainState: mainStatePDA, user: user.publicKey, treasurer: provider.publicKey})
+                .rpc();
+            err = "Referred subscription was withdrawn without the referrer balance";
+        } catch (e) {
+            if (!e.toString().includes("MissingObligatoryAccount")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+        await program.methods
+            .withdraw(null)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, treasurer: provider.publicKey, referrerBalance: referrerBalancePDA})
+            .rpc();
+        const balance = await program.account.referrerBalance.fetch(referrerBalancePDA);
+        const treasury = await program.account.treasury.fetch(treasuryPDA);
+        if (!balance.accrued.eq(price.divn(5))) throw new Error("Referral share is not accrued");
+        if (!treasury.referralOwed.eq(price.divn(5)) || !treasury.collected.eq(price.sub(price.divn(5)))) throw new Error("Treasury doesn't account the referral share");
+    });
+
+    it("Should let referrer claim the rewards from the treasury only once", async () => {
+        // PAUSE_TREASURY = 1 << 7 stops the claims
+        await program.methods
+            .setPause(1 << 7)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        let err = null;
+        try {
+            await program.methods
+                .claimReferralRewards()
+                .accounts({mainState: mainStatePDA, referrer: referrer.publicKey})
+                .signers([referrer])
+                .rpc();
+            err = "Rewards were claimed while the treasury is paused";
+        } catch (e) {
+            if (!e.toString().includes("InstructionPaused")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+        await program.methods
+            .setPause(0)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        const before = await provider.connection.getBalance(referrer.publicKey);
+        await program.methods
+            .claimReferralRewards()
+            .accounts({mainState: mainStatePDA, referrer: referrer.publicKey})
+            .signers([referrer])
+            .rpc();
+        const claimed = await provider.connection.getBalance(referrer.publicKey) - before;
+        if (claimed !== price.divn(5).toNumber()) throw new Error("Rewards are not paid to the referrer");
+        await program.methods
+            .claimReferralRewards()
+            .accounts({mainState: mainStatePDA, referrer: referrer.publicKey})
+            .signers([referrer])
+            .rpc();
+        if (await provider.connection.getBalance(referrer.publicKey) !== before + claimed) throw new Error("Rewards were claimed twice");
+        const treasury = await program.account.treasury.fetch(treasuryPDA);
+        if (!treasury.referralOwed.eqn(0)) throw new Error("Claimed rewards are still owed");
+    });
+
+    it("Should accrue the referral share of the period consumed before the sunset", async () => {
+        const [subsPDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId);
+        await program.methods
+            .fundSubscription(price)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .signers([user])
+            .rpc();
+        await program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc();
+        await wait(3000);
+        await program.methods
+            .startSunset()
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        const processSunset = (balance: anchor.web3.PublicKey) => program.methods
+            .processSunset()
+            .accounts({mainState: mainStatePDA, keeper: provider.publicKey})
+            .remainingAccounts([
+                {pubkey: subsPDA, isSigner: false, isWritable: true},
+                {pubkey: user.publicKey, isSigner: false, isWritable: true},
+                {pubkey: balance, isSigner: false, isWritable: !balance.equals(program.programId)},
+            ])
+            .rpc();
+        let err = null;
+        try {
+            await processSunset(program.programId);
+            err = "Referred subscription was refunded without the referrer balance";
+        } catch (e) {
+            if (!e.toString().includes("MissingObligatoryAccount")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+        const before = await program.account.referrerBalance.fetch(referrerBalancePDA);
+        await processSunset(referrerBalancePDA);
+        const after = await program.account.referrerBalance.fetch(referrerBalancePDA);
+        // the whole period was consumed before the sunset
+        if (!after.accrued.sub(before.accrued).eq(price.divn(5))) throw new Error("Referral share of the consumed period is not accrued");
+    });
+});
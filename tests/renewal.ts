@@ -0,0 +1,157 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
+import { LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
+
+
+describe("renewal", () => {
+    async function wait(ms: number) {
+        return new Promise(resolve => {
+            setTimeout(resolve, ms);
+        });
+    }
+    const provider = anchor.AnchorProvider.env()
+
+    anchor.setProvider(provider);
+
+    const idl = require("../target/idl/w_3_subs_tracker.json") as any;
+    const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    // own tenant, so the bounty applies before any subscription and doesn't affect the other tests
+    const tenantId = new BN(Date.now());
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    // short period plan, so the subscription expires during the test
+    const planId = new BN(1);
+    const [planPDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), planId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const price = new BN(LAMPORTS_PER_SOL * 0.1);
+    const bounty = new BN(LAMPORTS_PER_SOL * 0.001);
+    const user = anchor.web3.Keypair.generate();
+    const keeper = anchor.web3.Keypair.generate();
+    const [subsPDA, ___] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId);
+
+    it("Should init main state, short plan, queued bounty and active subscription", async () => {
+        await program.methods
+            .intializeMainState(tenantId, feeSchedule, configDelay)
+            .rpc();
+        for (const keypair of [user, keeper]) {
+            const tx = await provider.connection.requestAirdrop(keypair.publicKey, LAMPORTS_PER_SOL);
+            await provider.connection.confirmTransaction(tx);
+        }
+        await program.methods
+            .createPlan(planId, "short", price, new BN(2000), 1)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        await program.methods
+            .updateRenewBounty(bounty)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        // the bounty is paid only once the config delay passes
+        const mainState = await program.account.mainState.fetch(mainStatePDA);
+        if (!mainState.renewBountyLamports.eqn(0) || !mainState.pendingConfig.renewBountyLamports.eq(bounty)) throw new Error("Bounty should be only queued");
+        await program.methods
+            .createSubscription(price.muln(2), null)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc();
+        await program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc();
+    });
+
+    it("Shouldn't let keeper renew without auto renew or during active period", async () => {
+        let err = null;
+        await wait(3000);
+        try {
+            await program.methods
+                .renew()
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, keeper: keeper.publicKey})
+                .signers([keeper])
+                .rpc();
+            err = "Shouldn't renew subscription without auto renew";
+        } catch (error) {}
+        if (err) throw new Error(err);
+
+        let error = null;
+        try {
+            await program.methods
+                .setAutoRenew(true)
+                .accounts({mainState: mainStatePDA, user: user.publicKey})
+                .signers([keeper])
+                .rpc();
+            error = "Shouldn't let other signer than user enable auto renew";
+        } catch (e) {}
+        if (error) throw new Error(error);
+        await program.methods
+            .setAutoRenew(true)
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .signers([user])
+            .rpc();
+    });
+
+    it("Should let keeper renew the expired subscription without the queued bounty", async () => {
+        const before = await program.account.subscription.fetch(subsPDA);
+        const keeperBalanceBefore = await provider.connection.getBalance(keeper.publicKey);
+        await program.methods
+            .renew()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, keeper: keeper.publicKey})
+            .signers([keeper])
+            .rpc();
+        const after = await program.account.subscription.fetch(subsPDA);
+        const keeperBalanceAfter = await provider.connection.getBalance(keeper.publicKey);
+        if (!before.ledger.credits.sub(after.ledger.credits).eq(price)) throw new Error("Credits should be charged by the price only");
+        if (!after.ledger.locked.eq(price)) throw new Error("Debits should be equal to the plan price");
+        if (after.authorityWritable.validTill.toNumber() <= before.authorityWritable.validTill.toNumber()) throw new Error("Valid till should be extended");
+        // keeper pays the transaction fee
+        if (keeperBalanceAfter > keeperBalanceBefore) throw new Error("Keeper shouldn't receive the queued bounty");
+
+        let err = null;
+        try {
+            await program.methods
+                .renew()
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, keeper: keeper.publicKey})
+                .signers([keeper])
+                .rpc();
+            err = "Shouldn't renew during active period";
+        } catch (error) {}
+        if (err) throw new Error(err);
+    });
+
+    it("Should fail to renew with NotEnoughCredits when the credits are short", async () => {
+        await wait(3000);
+        let err = null;
+        try {
+            await program.methods
+                .renew()
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, keeper: keeper.publicKey})
+                .signers([keeper])
+                .rpc();
+            err = "Shouldn't renew without enough credits";
+        } catch (error) {
+            if (!error.toString().includes("NotEnoughCredits")) err = `Unexpected error: ${error}`;
+        }
+        if (err) throw new Error(err);
+    });
+
+    it("Should keep the bounty until the config delay passes", async () => {
+        await program.methods
+            .updateRenewBounty(bounty.muln(2))
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        let err = null;
+        try {
+            await program.methods
+                .applyPendingConfig()
+                .accounts({mainState: mainStatePDA})
+                .rpc();
+            err = "Bounty was changed before the config delay";
+        } catch (error) {
+            if (!error.toString().includes("ConfigNotEffective")) err = `Unexpected error: ${error}`;
+        }
+        if (err) throw new Error(err);
+        const mainState = await program.account.mainState.fetch(mainStatePDA);
+        if (!mainState.renewBountyLamports.eqn(0) || !mainState.pendingConfig.renewBountyLamports.eq(bounty.muln(2))) throw new Error("Bounty should be only queued");
+    });
+});
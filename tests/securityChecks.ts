@@ -4,6 +4,7 @@ import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
 import { Keypair, LAMPORTS_PER_SOL, PublicKey } from "@solana/web3.js";
 import { use } from "chai";
 import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
 
 
 describe("securityChecks", () => {
@@ -20,8 +21,13 @@ describe("securityChecks", () => {
 
   const idl = require("../target/idl/w_3_subs_tracker.json") as any;
   const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
-  const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState')], program.programId);
-  const [fakeStatePDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainStatee')], program.programId);
+  const tenantId = new BN(Date.now());
+  const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+  const basicPlanId = new BN(1);
+  const premiumPlanId = new BN(2);
+  const [basicPlanPDA, ___] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), basicPlanId.toArrayLike(Buffer, 'le', 8)], program.programId);
+  const [premiumPlanPDA, ____] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), premiumPlanId.toArrayLike(Buffer, 'le', 8)], program.programId);
+  const [fakeStatePDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainStatee'), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
 
   
     // INIT OF USERS, MAIN STATE, AIRDROPS
@@ -55,13 +61,23 @@ describe("securityChecks", () => {
         } catch {
             // if mainState does not exist, create it
             const tx = await program.methods
-                .intializeMainState(10)
+                .intializeMainState(tenantId, feeSchedule, configDelay)
                 .rpc({skipPreflight: true});
         }
+        for (const [planPDA, planId, name, price, tier] of [[basicPlanPDA, basicPlanId, "basic", 0.3, 1], [premiumPlanPDA, premiumPlanId, "premium", 0.4, 2]] as const) {
+            try {
+                await program.account.plan.fetch(planPDA);
+            } catch {
+                await program.methods
+                    .createPlan(planId, name, new BN(LAMPORTS_PER_SOL * price), new BN(1000 * 60 * 60 * 24 * 30), tier)
+                    .accounts({mainState: mainStatePDA})
+                    .rpc({skipPreflight: true});
+            }
+        }
         let err = null;
         try {
             await program.methods
-                    .intializeMainState(10)
+                    .intializeMainState(tenantId, feeSchedule, configDelay)
                     .accounts({mainState: fakeStatePDA, user: fakeProviders[0].publicKey})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
@@ -69,19 +85,25 @@ describe("securityChecks", () => {
         } catch {}
         if (err)  throw err;
         
+        // the same tenant id of another wallet derives its own main state, so it can't squat the one of the provider
+        const [fakeProviderStatePDA, _____] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), fakeProviders[0].publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
         try {
-                await program.methods
-                        .intializeMainState(10)
-                        .accounts({user: fakeProviders[0].publicKey})
-                        .signers([fakeProviders[0]])
-                        .rpc({skipPreflight: true});
-                        err = new Error("Should not have initialized the mainState when there is already one");
-        } catch {}
-            if (err)  throw err;
-            
+            await program.account.mainState.fetch(fakeProviderStatePDA);
+        } catch {
+            await program.methods
+                .intializeMainState(tenantId, feeSchedule, configDelay)
+                .accounts({user: fakeProviders[0].publicKey})
+                .signers([fakeProviders[0]])
+                .rpc();
+        }
+        const fakeProviderState = await program.account.mainState.fetch(fakeProviderStatePDA);
+        if (fakeProviderStatePDA.equals(mainStatePDA) || fakeProviderState.owner.toBase58() !== fakeProviders[0].publicKey.toBase58()) {
+            throw new Error("Other wallet's main state should be derived from its own key");
+        }
+
         try {
             const tx = await program.methods
-                .intializeMainState(10)
+                .intializeMainState(tenantId, feeSchedule, configDelay)
                 .rpc({skipPreflight: true});
             err = new Error("Should not have initialized the mainState when there is already one, even with correct owner/authority");
         }  catch {}
@@ -96,20 +118,29 @@ describe("securityChecks", () => {
     it("MainState authority should not be changed by anyone other than the owner", async () => {
         // at first change authority to some other legit provider
         const tx = await program.methods
-                .updateAuthority(someOtherLegitProvider.publicKey)
+                .proposeAuthority(someOtherLegitProvider.publicKey)
                 .accounts({mainState: mainStatePDA})
                 .rpc({skipPreflight: true});
+        await program.methods
+                .acceptAuthority()
+                .accounts({mainState: mainStatePDA, signer: someOtherLegitProvider.publicKey})
+                .signers([someOtherLegitProvider])
+                .rpc({skipPreflight: true});
 
         // revert it back
         const tx2 = await program.methods
-                .updateAuthority(provider.publicKey)
+                .proposeAuthority(provider.publicKey)
+                .accounts({mainState: mainStatePDA})
+                .rpc({skipPreflight: true});
+        await program.methods
+                .acceptAuthority()
                 .accounts({mainState: mainStatePDA})
                 .rpc({skipPreflight: true});
         // now try to change authority with wrong owner
         let err = null;
         try {
             await program.methods
-                    .updateAuthority(someOtherLegitProvider.publicKey)
+                    .proposeAuthority(someOtherLegitProvider.publicKey)
                     .accounts({mainState: mainStatePDA})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
@@ -119,7 +150,7 @@ describe("securityChecks", () => {
         // now try to change authority with wrong owner 2
         try {
             await program.methods
-                    .updateAuthority(someOtherLegitProvider.publicKey)
+                    .proposeAuthority(someOtherLegitProvider.publicKey)
                     .accounts({mainState: mainStatePDA})
                     .signers([usersKeyPairs[0]])
                     .rpc({skipPreflight: true});
@@ -141,16 +172,16 @@ describe("securityChecks", () => {
         const attackedKeyPair = usersKeyPairs[1];
 
         const tx = await program.methods
-                .createSubscription(new BN(3 * LAMPORTS_PER_SOL), {premium: {}})
-                .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                .createSubscription(new BN(3 * LAMPORTS_PER_SOL), null)
+                .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                 .signers([userKeyPair])
                 .rpc({skipPreflight: true});
         let err = null;
         // recreate the subs by same user
         try {
             await program.methods
-                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), {premium: {}})
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), null)
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
                     err = new Error("Should not have created the subscription twice, even by same user");
@@ -170,7 +201,7 @@ describe("securityChecks", () => {
         try {
             await program.methods
                     .withdraw(null)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, toAccount: userKeyPair.publicKey})
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, treasurer: provider.wallet.publicKey})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let user use withdraw functionality with correct mainState PDA");
@@ -181,7 +212,7 @@ describe("securityChecks", () => {
         try {
             await program.methods
                     .withdraw(null)
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, toAccount: userKeyPair.publicKey})
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, treasurer: provider.wallet.publicKey})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let user use withdraw functionality with wrong mainState PDA");
@@ -192,7 +223,7 @@ describe("securityChecks", () => {
         try {
             await program.methods
                     .withdraw(null)
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, toAccount: provider.publicKey})
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, treasurer: provider.wallet.publicKey})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use withdraw functionality ");
@@ -203,7 +234,7 @@ describe("securityChecks", () => {
         try {
             await program.methods
                     .withdraw(null)
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: fakeProviders[0].publicKey, toAccount: provider.publicKey})
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, treasurer: fakeProviders[0].publicKey})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use withdraw functionality ");
@@ -214,7 +245,7 @@ describe("securityChecks", () => {
         try {
             await program.methods
                     .withdraw(null)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, toAccount: userKeyPair.publicKey})
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, treasurer: provider.wallet.publicKey})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use withdraw functionality ");
@@ -225,7 +256,7 @@ describe("securityChecks", () => {
          try {
             await program.methods
                     .withdraw(null)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: fakeProviders[0].publicKey, toAccount: userKeyPair.publicKey})
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, treasurer: fakeProviders[0].publicKey})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use withdraw functionality ");
@@ -236,7 +267,7 @@ describe("securityChecks", () => {
         try {
             const tx = await program.methods
                     .withdraw(null)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, toAccount: userKeyPair.publicKey})
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, treasurer: provider.wallet.publicKey})
                     .rpc({skipPreflight: true});
             console.log(`Withdrawal was successful, previous state of subscription: ${JSON.stringify(subInfo, null, 2)} \n new state of the subscription: ${JSON.stringify(await program.account.subscription.fetch(pda), null ,2)}`);
         } catch(ex) {
@@ -245,18 +276,35 @@ describe("securityChecks", () => {
         }
     });
 
+    it("Shouldn't let authority withdraw lamports transferred directly to the subscription PDA", async () => {
+        const userKeyPair = usersKeyPairs[0];
+        const pda = subsPdas[0];
+        const tx = new anchor.web3.Transaction().add(anchor.web3.SystemProgram.transfer({fromPubkey: provider.wallet.publicKey, toPubkey: pda, lamports: LAMPORTS_PER_SOL * 0.05}));
+        await provider.sendAndConfirm(tx);
+        let err = null;
+        try {
+            await program.methods
+                    .withdraw(new BN(LAMPORTS_PER_SOL * 0.05))
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, treasurer: provider.wallet.publicKey})
+                    .rpc();
+            err = new Error("Should not have let authority withdraw funds which are not earned");
+        } catch(ex) {
+            if (!ex.toString().includes("WithdrawFromSubscription")) err = new Error(`Unexpected error: ${ex}`);
+        }
+        if (err)  throw err;
+    });
+
     it("Shouldn't let unauthorized signers to setSubscriptionInfo, only authority can", async () => {
         const userKeyPair = usersKeyPairs[0];
         const pda = subsPdas[0];
         let subInfo = await program.account.subscription.fetch(pda);
         console.log(`Current state of the subscription: ${JSON.stringify(subInfo, null, 2)}`);
         let err = null;
-        const premiumEnum = {premium: {}};
         // as user with correct mainState PDA
         try {
             await program.methods
-                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3), premiumEnum)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey})
+                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3))
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, plan: premiumPlanPDA})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let user use setSubscriptionInfo functionality with correct mainState PDA");
@@ -266,8 +314,8 @@ describe("securityChecks", () => {
         // as user with correct mainState PDA changing the authority to fakeProvider
         try {
             await program.methods
-                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3), premiumEnum)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: fakeProviders[0].publicKey})
+                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3))
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: fakeProviders[0].publicKey, plan: premiumPlanPDA})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let user use setSubscriptionInfo functionality with correct mainState PDA");
@@ -276,8 +324,8 @@ describe("securityChecks", () => {
         // as user with incorrect mainState PDA
         try {
             await program.methods
-                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3), premiumEnum)
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey})
+                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3))
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, plan: premiumPlanPDA})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let user use setSubscriptionInfo functionality with wrong mainState PDA");
@@ -287,8 +335,8 @@ describe("securityChecks", () => {
         // as user with fakeMainState PDA changing the authority to fakeProvider 
         try {
             await program.methods
-                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3), premiumEnum)
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: fakeProviders[0].publicKey})
+                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3))
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: fakeProviders[0].publicKey, plan: premiumPlanPDA})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let user use setSubscriptionInfo functionality with wrong mainState PDA");
@@ -297,8 +345,8 @@ describe("securityChecks", () => {
         // as fakeProvider (signer) with incorrect mainState PDA acting as correct authority
         try {
             await program.methods
-                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3), premiumEnum)
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey})
+                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3))
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, plan: premiumPlanPDA})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use setSubscriptionInfo functionality ");
@@ -308,8 +356,8 @@ describe("securityChecks", () => {
         // as fakeProvider (signer) with incorrect mainState PDA 
         try {
             await program.methods
-                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3), premiumEnum)
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: fakeProviders[0].publicKey})
+                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3))
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, authority: fakeProviders[0].publicKey, plan: premiumPlanPDA})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use setSubscriptionInfo functionality ");
@@ -319,8 +367,8 @@ describe("securityChecks", () => {
         // as correct authority
         try {
             await program.methods
-                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3), premiumEnum)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey})
+                    .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.3))
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, authority: provider.wallet.publicKey, plan: premiumPlanPDA})
                     .rpc({skipPreflight: true});
             console.log(`setSubscriptionInfo was successful, previous state of subscription: ${JSON.stringify(subInfo, null, 2)} \n new state of the subscription: ${JSON.stringify(await program.account.subscription.fetch(pda), null, 2)}`);
         } catch(ex) {
@@ -336,12 +384,11 @@ describe("securityChecks", () => {
         let subInfo = await program.account.subscription.fetch(pda);
         console.log(`Current state of the subscription: ${JSON.stringify(subInfo, null, 2)}`);
         let err = null;
-        const premiumEnum = {premium: {}};
         // as wrong signer, acting as correct user with correct mainState PDA
         try {
             await program.methods
-                    .changeDesiredSubscriptionType(premiumEnum)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                    .changeDesiredSubscriptionType()
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([wrongUserKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let user use changeDesiredSubscriptionType functionality with correct mainState PDA");
@@ -350,8 +397,8 @@ describe("securityChecks", () => {
         // as wrong signer, acting as correct user with wrong mainState PDA
         try {
             await program.methods
-                    .changeDesiredSubscriptionType(premiumEnum)
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey})
+                    .changeDesiredSubscriptionType()
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([wrongUserKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let user use changeDesiredSubscriptionType functionality with correct mainState PDA");
@@ -360,8 +407,8 @@ describe("securityChecks", () => {
         // as fakeProvider (signer) with correct mainState PDA
         try {
             await program.methods
-                    .changeDesiredSubscriptionType(premiumEnum)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                    .changeDesiredSubscriptionType()
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use changeDesiredSubscriptionType functionality ");
@@ -371,8 +418,8 @@ describe("securityChecks", () => {
         // as fakeProvider (signer) with wrong mainState PDA
         try {
             await program.methods
-                    .changeDesiredSubscriptionType(premiumEnum)
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey})
+                    .changeDesiredSubscriptionType()
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use changeDesiredSubscriptionType functionality ");
@@ -381,8 +428,8 @@ describe("securityChecks", () => {
         // as correct authority
         try {
             await program.methods
-                    .changeDesiredSubscriptionType(premiumEnum)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                    .changeDesiredSubscriptionType()
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let real provider use changeDesiredSubscriptionType functionality ");
         } catch(ex) { }
@@ -391,8 +438,8 @@ describe("securityChecks", () => {
         // as correct user
         try {
             await program.methods
-                    .changeDesiredSubscriptionType(premiumEnum)
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                    .changeDesiredSubscriptionType()
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             console.log(`changeDesiredSubscriptionType was successful, previous state of subscription: ${JSON.stringify(subInfo, null, 2)} \n new state of the subscription: ${JSON.stringify(await program.account.subscription.fetch(pda), null, 2)}`);
@@ -413,8 +460,8 @@ describe("securityChecks", () => {
         // as user with correct mainState PDA
         try {
             await program.methods
-                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), {premium: {}})
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), null)
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("PDA shouldn't be reinitialised, even by the creator");
@@ -424,8 +471,8 @@ describe("securityChecks", () => {
         // as user with wrong mainState PDA
         try {
             await program.methods
-                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), {premium: {}})
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey})
+                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), null)
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let user use createSubscription functionality with wrong mainState PDA");
@@ -435,8 +482,8 @@ describe("securityChecks", () => {
         // as fakeProvider (signer) with correct mainState PDA
         try {
             await program.methods
-                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), {premium: {}})
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), null)
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use createSubscription functionality for existing PDA ");
@@ -446,8 +493,8 @@ describe("securityChecks", () => {
         // as fakeProvider (signer) with wrong mainState PDA
         try {
             await program.methods
-                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), {premium: {}})
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey})
+                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), null)
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use createSubscription functionality for existing PDA with fakeStatePDA ");
@@ -457,8 +504,8 @@ describe("securityChecks", () => {
         // as correct authority
         try {
             await program.methods
-                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), {premium: {}})
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), null)
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: premiumPlanPDA})
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let real provider use createSubscription functionality for existing PDA");
         } catch(ex) { console.log(`Questioning error5: ${ex}`) }
@@ -466,8 +513,8 @@ describe("securityChecks", () => {
         // as another user, for himself
         try {
             await program.methods
-                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), {premium: {}})
-                    .accounts({mainState: mainStatePDA, user: anotherUserKeyPair.publicKey})
+                    .createSubscription(new BN(0.1 * LAMPORTS_PER_SOL), null)
+                    .accounts({mainState: mainStatePDA, user: anotherUserKeyPair.publicKey, plan: premiumPlanPDA})
                     .signers([anotherUserKeyPair])
                     .rpc({skipPreflight: true});
         const pda1 = subsPdas[1];
@@ -571,8 +618,8 @@ describe("securityChecks", () => {
         // as correct user with wrong mainState PDA
         try {
             await program.methods
-                    .unsubscribe(shouldWithdrawLamports, {basic: {}})
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, toAccount: null, mainStateOwner: provider.wallet.publicKey})
+                    .unsubscribe(shouldWithdrawLamports)
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, toAccount: null, newDesiredPlan: basicPlanPDA})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let user use unsubscribe functionality with wrong mainState PDA");
@@ -581,8 +628,8 @@ describe("securityChecks", () => {
         // as fakeProvider (signer) with correct mainState PDA
         try {
             await program.methods
-                    .unsubscribe(shouldWithdrawLamports, {basic: {}})
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, toAccount: null, mainStateOwner: provider.wallet.publicKey})
+                    .unsubscribe(shouldWithdrawLamports)
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, toAccount: null, newDesiredPlan: basicPlanPDA})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use unsubscribe functionality ");
@@ -593,8 +640,8 @@ describe("securityChecks", () => {
         // as fakeProvider (signer) with wrong mainState PDA
         try {
             await program.methods
-                    .unsubscribe(shouldWithdrawLamports, {basic: {}})
-                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, toAccount: null, mainStateOwner: provider.wallet.publicKey})
+                    .unsubscribe(shouldWithdrawLamports)
+                    .accounts({mainState: fakeStatePDA, user: userKeyPair.publicKey, toAccount: null, newDesiredPlan: basicPlanPDA})
                     .signers([fakeProviders[0]])
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let fake provider use unsubscribe functionality ");
@@ -603,8 +650,8 @@ describe("securityChecks", () => {
         // as correct authority with correct mainState PDA
         try {
             await program.methods
-                    .unsubscribe(shouldWithdrawLamports, {basic: {}})
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, toAccount: null, mainStateOwner: provider.wallet.publicKey})
+                    .unsubscribe(shouldWithdrawLamports)
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, toAccount: null, newDesiredPlan: basicPlanPDA})
                     .rpc({skipPreflight: true});
             err = new Error("Should not have let real provider use unsubscribe functionality ");
         } catch(ex) { }
@@ -612,8 +659,8 @@ describe("securityChecks", () => {
         // as correct user with correct mainState PDA
         try {
             await program.methods
-                    .unsubscribe(shouldWithdrawLamports, {basic: {}})
-                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, toAccount: null, mainStateOwner: provider.wallet.publicKey})
+                    .unsubscribe(shouldWithdrawLamports)
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, toAccount: null, newDesiredPlan: basicPlanPDA})
                     .signers([userKeyPair])
                     .rpc({skipPreflight: true});
             console.log(`unsubscribe was successful, previous state of subscription: ${JSON.stringify(subInfo, null, 2)} \n new state of the subscription: ${JSON.stringify(await program.account.subscription.fetch(pda), null, 2)}`);
@@ -624,8 +671,8 @@ describe("securityChecks", () => {
         // as correct other user with correct mainState PDA
         try {
             await program.methods
-                    .unsubscribe(shouldWithdrawLamports, {basic: {}})
-                    .accounts({mainState: mainStatePDA, user: anotherUserKeyPair.publicKey, toAccount: null, mainStateOwner: provider.wallet.publicKey})
+                    .unsubscribe(shouldWithdrawLamports)
+                    .accounts({mainState: mainStatePDA, user: anotherUserKeyPair.publicKey, toAccount: null, newDesiredPlan: basicPlanPDA})
                     .signers([anotherUserKeyPair])
                     .rpc({skipPreflight: true});
             console.log(`unsubscribe was successful, previous state of subscription: ${JSON.stringify(anotherSubInfo, null, 2)} \n new state of the subscription: ${JSON.stringify(await program.account.subscription.fetch(subsPdas[1]), null, 2)}`);
@@ -634,4 +681,64 @@ describe("securityChecks", () => {
             throw new Error("Should have let correct user use unsubscribe functionality");
         }
     });
+
+    it("Should block paused instructions but let the user take the credits out while unsubscribe is paused", async () => {
+        const userKeyPair = usersKeyPairs[0];
+        const pda = subsPdas[0];
+        let err = null;
+        // the pause flags are PAUSE_FUND = 1 << 1 and PAUSE_UNSUBSCRIBE = 1 << 4
+        try {
+            await program.methods
+                    .setPause((1 << 1) | (1 << 4))
+                    .accounts({mainState: mainStatePDA, signer: fakeProviders[0].publicKey})
+                    .signers([fakeProviders[0]])
+                    .rpc();
+            err = new Error("Should not have let fake provider pause the program");
+        } catch(ex) { }
+        if (err) throw err;
+
+        try {
+            await program.methods
+                    .emergencyWithdrawCredits()
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                    .signers([userKeyPair])
+                    .rpc();
+            err = new Error("Should not have let user use the emergency withdrawal while unsubscribe is not paused");
+        } catch(ex) { }
+        if (err) throw err;
+
+        await program.methods
+                .setPause((1 << 1) | (1 << 4))
+                .accounts({mainState: mainStatePDA})
+                .rpc();
+        try {
+            try {
+                await program.methods
+                        .fundSubscription(new BN(0.1 * LAMPORTS_PER_SOL))
+                        .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                        .signers([userKeyPair])
+                        .rpc();
+                err = new Error("Should not have let user fund the subscription while funding is paused");
+            } catch(ex) { }
+            if (err) throw err;
+
+            const credits = (await program.account.subscription.fetch(pda)).ledger.credits;
+            const beforeBalance = await provider.connection.getBalance(userKeyPair.publicKey);
+            await program.methods
+                    .emergencyWithdrawCredits()
+                    .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+                    .signers([userKeyPair])
+                    .rpc();
+            const afterBalance = await provider.connection.getBalance(userKeyPair.publicKey);
+            const subInfo = await program.account.subscription.fetch(pda);
+            if (!subInfo.ledger.credits.isZero() || afterBalance + 10000 < beforeBalance + credits.toNumber()) {
+                throw new Error("Credits were not paid back to the user");
+            }
+        } finally {
+            await program.methods
+                    .setPause(0)
+                    .accounts({mainState: mainStatePDA})
+                    .rpc();
+        }
+    });
 });
\ No newline at end of file
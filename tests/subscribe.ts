@@ -4,6 +4,7 @@ import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
 import { Keypair, LAMPORTS_PER_SOL, PublicKey } from "@solana/web3.js";
 import { BN } from "bn.js";
 import { use } from "chai";
+import { configDelay, feeSchedule } from "./config";
 
 describe("subscribe", async () => {
     async function wait(ms: number) {
@@ -20,10 +21,15 @@ describe("subscribe", async () => {
     
     const idl = require("../target/idl/w_3_subs_tracker.json") as any;
     const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    const tenantId = new BN(Date.now());
     const usersKeyPairs = [anchor.web3.Keypair.generate(),  anchor.web3.Keypair.generate()];
         // , anchor.web3.Keypair.generate(), anchor.web3.Keypair.generate(), anchor.web3.Keypair.generate(), anchor.web3.Keypair.generate()];
 
-    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState')], program.programId);
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const basicPlanId = new BN(1);
+    const premiumPlanId = new BN(2);
+    const [basicPlanPDA, ___] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), basicPlanId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const [premiumPlanPDA, ____] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), premiumPlanId.toArrayLike(Buffer, 'le', 8)], program.programId);
     const subsPdas = [];
 
     for(let i = 0; i < usersKeyPairs.length; i++) {
@@ -40,9 +46,19 @@ describe("subscribe", async () => {
         } catch {
             // if mainState does not exist, create it
             const tx = await program.methods
-                .intializeMainState(10)
+                .intializeMainState(tenantId, feeSchedule, configDelay)
                 .rpc({skipPreflight: true});
         }
+        for (const [planPDA, planId, name, price, tier] of [[basicPlanPDA, basicPlanId, "basic", 0.3, 1], [premiumPlanPDA, premiumPlanId, "premium", 0.4, 2]] as const) {
+            try {
+                await program.account.plan.fetch(planPDA);
+            } catch {
+                await program.methods
+                    .createPlan(planId, name, new BN(LAMPORTS_PER_SOL * price), new BN(1000 * 60 * 60 * 24 * 30), tier)
+                    .accounts({mainState: mainStatePDA})
+                    .rpc({skipPreflight: true});
+            }
+        }
         const mainState = await program.account.mainState.fetch(mainStatePDA);
         console.log("Main state: ", JSON.stringify(mainState));
         if( mainState.authority.toBase58() !== provider.wallet.publicKey.toBase58() ) {
@@ -63,12 +79,11 @@ describe("subscribe", async () => {
         
         for(let i = 0; i < usersKeyPairs.length; i++) {
             const userKeyPair = usersKeyPairs[i];
-            const d = {basic: {}};
             
             const tx = await program.methods
 
-            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.25), d)
-            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey})
+            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.25), null)
+            .accounts({mainState: mainStatePDA, user: userKeyPair.publicKey, plan: basicPlanPDA})
             .signers([userKeyPair])
             .rpc();
             const pda = subsPdas[i];
@@ -83,11 +98,12 @@ describe("subscribe", async () => {
         const pda = subsPdas[0];
         const futureDate = new BN(Date.now() + 1000 * 60 * 60 * 24 * 30);
         await program.methods
-            .setSubscriptionInfo(new BN(futureDate), null, null)
+            .setSubscriptionInfo(new BN(futureDate), null)
             .accounts({
                 mainState: mainStatePDA, 
                 authority: provider.wallet.publicKey,
-                user: user1.publicKey
+                user: user1.publicKey,
+                plan: null
             })
             .rpc();
         const subsInfo = await program.account.subscription.fetch(pda);
@@ -100,37 +116,48 @@ describe("subscribe", async () => {
         let err = null;
         try {
             await program.methods
-                .setSubscriptionInfo(new BN(futureDate), null , null)
+                .setSubscriptionInfo(new BN(futureDate), null)
                 .accounts({
                     mainState: mainStatePDA, 
                     authority: fakeProvider.publicKey,
-                    user: user2.publicKey
+                    user: user2.publicKey,
+                    plan: null
                 })
                 .signers([fakeProvider])
                 .rpc();
             err = "Shouldn't let make second user as active subscriber, due to fakeProvider";
         } catch (error) {
             const tx = await program.methods
-                .updateAuthority(fakeProvider.publicKey)
+                .proposeAuthority(fakeProvider.publicKey)
                 .accounts({
                     mainState: mainStatePDA
                 })
                 .rpc();
             await program.methods
-                .setSubscriptionInfo(new BN(futureDate), null, null)
+                .acceptAuthority()
+                .accounts({mainState: mainStatePDA, signer: fakeProvider.publicKey})
+                .signers([fakeProvider])
+                .rpc();
+            await program.methods
+                .setSubscriptionInfo(new BN(futureDate), null)
                 .accounts({
                     mainState: mainStatePDA, 
                     authority: fakeProvider.publicKey,
-                    user: user2.publicKey
+                    user: user2.publicKey,
+                    plan: null
                 })
                 .signers([fakeProvider])
                 .rpc();
             const tx1 = await program.methods
-                .updateAuthority(provider.wallet.publicKey)
+                .proposeAuthority(provider.wallet.publicKey)
                 .accounts({
                     mainState: mainStatePDA
                 })
                 .rpc();
+            await program.methods
+                .acceptAuthority()
+                .accounts({mainState: mainStatePDA})
+                .rpc();
         }
         if (err) throw new Error(err);
     })  
@@ -161,7 +188,7 @@ describe("subscribe", async () => {
             const tx = await program.methods
                 .withdraw(new BN(LAMPORTS_PER_SOL * 0.1))
                 .accounts({mainState: mainStatePDA, user: user1.publicKey,
-                        authority: provider.publicKey, toAccount: provider.publicKey})
+                        treasurer: provider.publicKey})
                 .rpc();
             error = "Should not withdraw any funds from PDA account as authority because they are used as credits";
 
@@ -171,7 +198,7 @@ describe("subscribe", async () => {
         const tx1 = await program.methods
             .withdraw(null)
             .accounts({mainState: mainStatePDA, user: user1.publicKey,
-                    authority: provider.publicKey, toAccount: provider.publicKey})
+                    treasurer: provider.publicKey})
             .rpc();
         const balanceOfPdaAfter = await provider.connection.getBalance(pda);
 
@@ -185,15 +212,15 @@ describe("subscribe", async () => {
         let err = null;
 
         await program.methods
-            .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.1), null)
-            .accounts({mainState: mainStatePDA, user: user2.publicKey, authority: provider.wallet.publicKey})
+            .setSubscriptionInfo(new BN(Date.now() + 1000), new BN(LAMPORTS_PER_SOL * 0.1))
+            .accounts({mainState: mainStatePDA, user: user2.publicKey, authority: provider.wallet.publicKey, plan: null})
             .rpc();
         await wait(2000);
         try {
             const tx = await program.methods
                 .withdraw(new BN(LAMPORTS_PER_SOL * 0.1))
                 .accounts({mainState: mainStatePDA, user: user2.publicKey,
-                        authority: provider.publicKey, toAccount: user2.publicKey})
+                        treasurer: provider.publicKey})
                 .signers([user2])
                 .rpc();
             err = "Shouldn't let withdraw funds from PDA account as user";
@@ -203,7 +230,7 @@ describe("subscribe", async () => {
                 const tx = await program.methods
                     .withdraw(new BN(LAMPORTS_PER_SOL * 0.1))
                     .accounts({mainState: mainStatePDA, user: user2.publicKey,
-                            authority: provider.publicKey, toAccount: fakeProvider.publicKey})
+                            treasurer: provider.publicKey})
                     .signers([fakeProvider])
                     .rpc();
                 err = "Shouldn't let withdraw funds from PDA account as fakeProvider";
@@ -214,7 +241,7 @@ describe("subscribe", async () => {
             const tx = await program.methods
                 .withdraw(new BN(LAMPORTS_PER_SOL * 0.1))
                 .accounts({mainState: mainStatePDA, user: user2.publicKey,
-                        authority: user2.publicKey, toAccount: user2.publicKey})
+                        treasurer: user2.publicKey})
                 .signers([user2])
                 .rpc();
             err = "Shouldn't let withdraw funds from PDA account as user";
@@ -224,7 +251,7 @@ describe("subscribe", async () => {
                 const tx = await program.methods
                     .withdraw(new BN(LAMPORTS_PER_SOL * 0.1))
                     .accounts({mainState: mainStatePDA, user: user2.publicKey,
-                            authority: fakeProvider.publicKey, toAccount: fakeProvider.publicKey})
+                            treasurer: fakeProvider.publicKey})
                     .signers([fakeProvider])
                     .rpc();
                 err = "Shouldn't let withdraw funds from PDA account as fakeProvider";
@@ -237,5 +264,104 @@ describe("subscribe", async () => {
     
 
     
+    it("Should let user activate his desired plan by himself only with enough credits and no active period", async () => {
+        const user = anchor.web3.Keypair.generate();
+        const [pda, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId);
+        const airdrop = await provider.connection.requestAirdrop(user.publicKey, LAMPORTS_PER_SOL);
+        await provider.connection.confirmTransaction(airdrop);
+        const plan = await program.account.plan.fetch(basicPlanPDA);
+
+        await program.methods
+            .createSubscription(plan.priceLamports.subn(1), null)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: basicPlanPDA})
+            .signers([user])
+            .rpc();
+        let err = null;
+        try {
+            await program.methods
+                .activateSubscription()
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: basicPlanPDA})
+                .signers([user])
+                .rpc();
+            err = "Shouldn't let user activate the plan without enough credits";
+        } catch (error) {}
+        if (err) throw new Error(err);
+
+        await program.methods
+            .fundSubscription(new BN(1))
+            .accounts({mainState: mainStatePDA, user: user.publicKey})
+            .signers([user])
+            .rpc();
+        try {
+            await program.methods
+                .activateSubscription()
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: premiumPlanPDA})
+                .signers([user])
+                .rpc();
+            err = "Shouldn't let user activate other plan than the desired one";
+        } catch (error) {}
+        if (err) throw new Error(err);
+
+        await program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: basicPlanPDA})
+            .signers([user])
+            .rpc();
+        const subsInfo = await program.account.subscription.fetch(pda);
+        if (subsInfo.ledger.credits.toNumber() !== 0) throw new Error("Credits should be moved to debits");
+        if (!subsInfo.ledger.locked.eq(plan.priceLamports)) throw new Error("Debits should be equal to the plan price");
+        if (subsInfo.authorityWritable.currentPlan.toBase58() !== basicPlanPDA.toBase58()) throw new Error("Current plan should be the desired plan");
+        if (!subsInfo.authorityWritable.validTill.sub(subsInfo.subscriptionStatusWritable.afterVerifyUtcTimestamp).eq(plan.period)) throw new Error("Valid till should be set from the plan period");
+
+        try {
+            await program.methods
+                .activateSubscription()
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: basicPlanPDA})
+                .signers([user])
+                .rpc();
+            err = "Shouldn't let user activate the plan during an active period";
+        } catch (error) {}
+        if (err) throw new Error(err);
+    });
+
+    it("Should verify access of the active subscription by the tier of its current plan", async () => {
+        const subsInfo = await program.account.subscription.fetch(pda);
+        const validTill = await program.methods
+            .verifyAccess(1)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: basicPlanPDA})
+            .view();
+        if (!validTill.eq(subsInfo.authorityWritable.validTill)) throw new Error("Verify access should return the valid till timestamp");
+
+        let err = null;
+        try {
+            await program.methods
+                .verifyAccess(2)
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: basicPlanPDA})
+                .view();
+            err = "Shouldn't verify access to the higher tier than the current plan";
+        } catch (error) {
+            if (!error.toString().includes("InsufficientTier")) err = `Unexpected error: ${error}`;
+        }
+        if (err) throw new Error(err);
+    });
+
+
+    it("Should create subscriptions in the current layout version and refuse to migrate them again", async () => {
+        const pda = subsPdas[0];
+        const subsInfo = await program.account.subscription.fetch(pda);
+        if (subsInfo.version !== 1) throw new Error("Subscription is not created with the current layout version");
+        let err = null;
+        try {
+            await program.methods
+                .migrateSubscription()
+                .accounts({subscription: pda, mainState: mainStatePDA, authority: provider.wallet.publicKey, currentPlan: null, desiredPlan: null})
+                .rpc();
+            err = "Subscription in the current layout was migrated again";
+        } catch (e) {
+            if (!e.toString().includes("AlreadyMigrated")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+    });
+
 })
 
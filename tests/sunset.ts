@@ -0,0 +1,173 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
+import { LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
+
+
+describe("sunset", () => {
+    const provider = anchor.AnchorProvider.env()
+
+    anchor.setProvider(provider);
+
+    const idl = require("../target/idl/w_3_subs_tracker.json") as any;
+    const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    // own tenant, the sunset closes its main state at the end
+    const tenantId = new BN(Date.now());
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const planId = new BN(1);
+    const [planPDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), planId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const price = new BN(LAMPORTS_PER_SOL * 0.1);
+    const users = [anchor.web3.Keypair.generate(), anchor.web3.Keypair.generate()];
+    const subsPdas = users.map(user => anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId)[0]);
+
+    it("Should init main state with an active and an idle subscription", async () => {
+        await program.methods
+            .intializeMainState(tenantId, feeSchedule, configDelay)
+            .rpc();
+        await program.methods
+            .createPlan(planId, "basic", price, new BN(1000 * 60 * 60 * 24 * 30), 1)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        for (const user of users) {
+            const tx = await provider.connection.requestAirdrop(user.publicKey, LAMPORTS_PER_SOL);
+            await provider.connection.confirmTransaction(tx);
+            await program.methods
+                .createSubscription(price.muln(2), null)
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+                .signers([user])
+                .rpc();
+        }
+        await program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: users[0].publicKey, plan: planPDA})
+            .signers([users[0]])
+            .rpc();
+        const mainState = await program.account.mainState.fetch(mainStatePDA);
+        if (mainState.openSubscriptions.toNumber() !== 2) throw new Error("Open subscriptions are not counted");
+    });
+
+    it("Shouldn't close the main state before the sunset or let other than owner start it", async () => {
+        let err = null;
+        try {
+            await program.methods
+                .closeMainState()
+                .accounts({mainState: mainStatePDA})
+                .rpc();
+            err = "Main state was closed without the sunset";
+        } catch (e) {
+            if (!e.toString().includes("SunsetNotStarted")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+
+        try {
+            await program.methods
+                .startSunset()
+                .accounts({mainState: mainStatePDA, signer: users[0].publicKey})
+                .signers([users[0]])
+                .rpc();
+            err = "Sunset was started by other than owner";
+        } catch (e) {}
+        if (err) throw new Error(err);
+    });
+
+    it("Should block new subscriptions and deposits after the sunset started", async () => {
+        await program.methods
+            .startSunset()
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        let err = null;
+        try {
+            await program.methods
+                .fundSubscription(price)
+                .accounts({mainState: mainStatePDA, user: users[1].publicKey})
+                .signers([users[1]])
+                .rpc();
+            err = "Subscription was funded after the sunset started";
+        } catch (e) {
+            if (!e.toString().includes("SunsetStarted")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+    });
+
+    it("Should let user unsubscribe without fees after the sunset started", async () => {
+        await program.methods
+            .unsubscribe(false)
+            .accounts({mainState: mainStatePDA, user: users[0].publicKey, toAccount: null, newDesiredPlan: null})
+            .signers([users[0]])
+            .rpc();
+        const subscription = await program.account.subscription.fetch(subsPdas[0]);
+        if (!subscription.ledger.feesOwed.eqn(0)) throw new Error("Downgrade fee was charged after the sunset started");
+    });
+
+    it("Should let keeper refund and close the subscriptions in a batch without fees", async () => {
+        const keeper = anchor.web3.Keypair.generate();
+        const balancesBefore = await Promise.all(users.map(user => provider.connection.getBalance(user.publicKey)));
+        const processSunset = () => program.methods
+            .processSunset()
+            .accounts({mainState: mainStatePDA, keeper: keeper.publicKey})
+            .remainingAccounts(users.flatMap((user, i) => [
+                {pubkey: subsPdas[i], isSigner: false, isWritable: true},
+                {pubkey: user.publicKey, isSigner: false, isWritable: true},
+                // no referrer
+                {pubkey: program.programId, isSigner: false, isWritable: false},
+            ]))
+            .signers([keeper])
+            .rpc();
+        await program.methods
+            .setPause(1 << 8)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        let err = null;
+        try {
+            await processSunset();
+            err = "Subscriptions were refunded while the sunset is paused";
+        } catch (e) {
+            if (!e.toString().includes("InstructionPaused")) err = `Unexpected error: ${e}`;
+        }
+        if (err) throw new Error(err);
+        await program.methods
+            .setPause(0)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        await processSunset();
+        for (let i = 0; i < users.length; i++) {
+            if (await provider.connection.getAccountInfo(subsPdas[i]) !== null) throw new Error("Subscription was not closed");
+            const refunded = await provider.connection.getBalance(users[i].publicKey) - balancesBefore[i];
+            // idle user gets all credits back, unsubscribed user the credits and nearly the whole unused period
+            if (refunded < price.muln(2).toNumber() - LAMPORTS_PER_SOL * 0.0001) throw new Error(`User ${i} was not refunded without fees`);
+        }
+        const mainState = await program.account.mainState.fetch(mainStatePDA);
+        if (mainState.openSubscriptions.toNumber() !== 0) throw new Error("Closed subscriptions are still counted");
+    });
+
+    it("Should close the main state once the treasury is swept", async () => {
+        const [treasuryPDA, ___] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('treasury'), mainStatePDA.toBuffer()], program.programId);
+        const treasury = await program.account.treasury.fetch(treasuryPDA);
+        // consumed part of the terminated period, unless the sunset ran within the same second as the activation
+        if (treasury.collected.gt(treasury.swept)) {
+            let err = null;
+            try {
+                await program.methods
+                    .closeMainState()
+                    .accounts({mainState: mainStatePDA})
+                    .rpc();
+                err = "Main state was closed with funds in the treasury";
+            } catch (e) {
+                if (!e.toString().includes("TreasuryNotSwept")) err = `Unexpected error: ${e}`;
+            }
+            if (err) throw new Error(err);
+        }
+
+        await program.methods
+            .sweepTreasury(null)
+            .accounts({mainState: mainStatePDA, payout: provider.wallet.publicKey})
+            .rpc();
+        await program.methods
+            .closeMainState()
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        if (await provider.connection.getAccountInfo(mainStatePDA) !== null) throw new Error("Main state was not closed");
+    });
+});
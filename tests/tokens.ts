@@ -0,0 +1,188 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
+import { LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { createMint, getAccount, getOrCreateAssociatedTokenAccount, mintTo, TOKEN_PROGRAM_ID, transfer } from "@solana/spl-token";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
+
+
+describe("tokens", () => {
+    const provider = anchor.AnchorProvider.env()
+
+    anchor.setProvider(provider);
+
+    const idl = require("../target/idl/w_3_subs_tracker.json") as any;
+    const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    const payer = (provider.wallet as anchor.Wallet).payer;
+    // own tenant accepting the SPL token, so the native SOL tenant of other tests is not affected
+    const tenantId = new BN(Date.now());
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const planId = new BN(1);
+    const [planPDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), planId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const price = new BN(1_000_000);
+    const user = anchor.web3.Keypair.generate();
+    const [subsPDA, ___] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId);
+    const [vaultPDA, ____] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('vault'), subsPDA.toBuffer()], program.programId);
+    const [treasuryVaultPDA, _____] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('treasury_vault'), mainStatePDA.toBuffer()], program.programId);
+    const [treasuryPDA, ______] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('treasury'), mainStatePDA.toBuffer()], program.programId);
+    let mint: anchor.web3.PublicKey;
+    let userTokenAccount: anchor.web3.PublicKey;
+    let ownerTokenAccount: anchor.web3.PublicKey;
+
+    it("Should init main state with accepted mint", async () => {
+        const tx = await provider.connection.requestAirdrop(user.publicKey, LAMPORTS_PER_SOL);
+        await provider.connection.confirmTransaction(tx);
+        mint = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+        userTokenAccount = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, mint, user.publicKey)).address;
+        ownerTokenAccount = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, mint, payer.publicKey)).address;
+        await mintTo(provider.connection, payer, mint, userTokenAccount, payer, price.muln(3).toNumber());
+
+        await program.methods
+            .intializeMainState(tenantId, feeSchedule, configDelay)
+            .accounts({acceptedMint: mint, treasuryVault: treasuryVaultPDA, tokenProgram: TOKEN_PROGRAM_ID})
+            .rpc();
+        const mainState = await program.account.mainState.fetch(mainStatePDA);
+        if (mainState.acceptedMint.toBase58() !== mint.toBase58()) throw new Error("Accepted mint was not set");
+        await program.methods
+            .createPlan(planId, "tokens", price, new BN(1000 * 60 * 60 * 24 * 30), 1)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+    });
+
+    it("Shouldn't create subscription paid in SOL for the main state with accepted mint", async () => {
+        let err = null;
+        try {
+            await program.methods
+                .createSubscription(price, null)
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+                .signers([user])
+                .rpc();
+            err = "Shouldn't create subscription without the mint";
+        } catch (error) {
+            if (!error.toString().includes("InvalidMint")) err = `Unexpected error: ${error}`;
+        }
+        if (err) throw new Error(err);
+    });
+
+    it("Should create, fund and activate subscription paid in tokens", async () => {
+        await program.methods
+            .createSubscription(price, null)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, mint, vault: vaultPDA, userTokenAccount, tokenProgram: TOKEN_PROGRAM_ID})
+            .signers([user])
+            .rpc();
+        await program.methods
+            .fundSubscription(price)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, vault: vaultPDA, userTokenAccount, tokenProgram: TOKEN_PROGRAM_ID})
+            .signers([user])
+            .rpc();
+        const vault = await getAccount(provider.connection, vaultPDA);
+        if (vault.amount !== BigInt(price.muln(2).toNumber())) throw new Error("Vault should hold the deposited tokens");
+        if (!vault.owner.equals(subsPDA)) throw new Error("Vault should be owned by the subscription");
+
+        await program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc();
+        const subscription = await program.account.subscription.fetch(subsPDA);
+        if (!subscription.ledger.credits.eq(price)) throw new Error("Credits should be charged by the plan price");
+    });
+
+    it("Should refund tokens to the user and the fee to the treasury on unsubscribe", async () => {
+        const userBefore = await getAccount(provider.connection, userTokenAccount);
+        await program.methods
+            .unsubscribe(true)
+            .accounts({
+                mainState: mainStatePDA,
+                user: user.publicKey,
+                toAccount: user.publicKey,
+                vault: vaultPDA,
+                toTokenAccount: userTokenAccount,
+                treasuryVault: treasuryVaultPDA,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .signers([user])
+            .rpc();
+        const userAfter = await getAccount(provider.connection, userTokenAccount);
+        const treasuryVault = await getAccount(provider.connection, treasuryVaultPDA);
+        const vault = await getAccount(provider.connection, vaultPDA);
+        if (userAfter.amount - userBefore.amount < BigInt(price.toNumber())) throw new Error("User should get at least the credits back");
+        if (vault.amount !== BigInt(0)) throw new Error("Vault should be emptied");
+        if (userAfter.amount - userBefore.amount + treasuryVault.amount !== BigInt(price.muln(2).toNumber())) throw new Error("All tokens should be paid out");
+    });
+
+    it("Should sweep the treasury only to the payout address", async () => {
+        const treasuryBefore = await getAccount(provider.connection, treasuryVaultPDA);
+        const ownerBefore = await getAccount(provider.connection, ownerTokenAccount);
+        let err = null;
+        try {
+            await program.methods
+                .sweepTreasury(null)
+                .accounts({mainState: mainStatePDA, payout: user.publicKey, treasuryVault: treasuryVaultPDA, payoutTokenAccount: userTokenAccount, tokenProgram: TOKEN_PROGRAM_ID})
+                .rpc();
+            err = "Treasury was swept to other address than the payout address";
+        } catch (error) {}
+        if (err) throw new Error(err);
+
+        await program.methods
+            .sweepTreasury(null)
+            .accounts({mainState: mainStatePDA, payout: payer.publicKey, treasuryVault: treasuryVaultPDA, payoutTokenAccount: ownerTokenAccount, tokenProgram: TOKEN_PROGRAM_ID})
+            .rpc();
+        const treasuryAfter = await getAccount(provider.connection, treasuryVaultPDA);
+        const ownerAfter = await getAccount(provider.connection, ownerTokenAccount);
+        if (treasuryAfter.amount !== BigInt(0) || ownerAfter.amount - ownerBefore.amount !== treasuryBefore.amount) throw new Error("Treasury should be swept to the payout address");
+    });
+
+    it("Should close the subscription with tokens sent to the vault directly", async () => {
+        const dust = 1000;
+        await transfer(provider.connection, user, userTokenAccount, vaultPDA, user, dust);
+        const treasuryBefore = await program.account.treasury.fetch(treasuryPDA);
+        await program.methods
+            .closeSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, vault: vaultPDA, treasuryVault: treasuryVaultPDA, tokenProgram: TOKEN_PROGRAM_ID})
+            .signers([user])
+            .rpc();
+        if (await provider.connection.getAccountInfo(vaultPDA) !== null) throw new Error("Vault was not closed");
+        const treasuryVault = await getAccount(provider.connection, treasuryVaultPDA);
+        const treasuryAfter = await program.account.treasury.fetch(treasuryPDA);
+        if (treasuryVault.amount !== BigInt(dust) || !treasuryAfter.collected.sub(treasuryBefore.collected).eqn(dust)) throw new Error("Treasury should collect the tokens sent to the vault");
+    });
+
+    it("Should sunset and close the main state with tokens sent to the vaults directly", async () => {
+        const dust = 1000;
+        await program.methods
+            .createSubscription(price, null)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, mint, vault: vaultPDA, userTokenAccount, tokenProgram: TOKEN_PROGRAM_ID})
+            .signers([user])
+            .rpc();
+        await program.methods
+            .startSunset()
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        await transfer(provider.connection, user, userTokenAccount, vaultPDA, user, dust);
+        const userBefore = await getAccount(provider.connection, userTokenAccount);
+        await program.methods
+            .processSunset()
+            .accounts({mainState: mainStatePDA, keeper: payer.publicKey, treasuryVault: treasuryVaultPDA, tokenProgram: TOKEN_PROGRAM_ID})
+            .remainingAccounts([subsPDA, user.publicKey, program.programId, vaultPDA, userTokenAccount].map(pubkey => ({pubkey, isSigner: false, isWritable: !pubkey.equals(program.programId)})))
+            .rpc();
+        const userAfter = await getAccount(provider.connection, userTokenAccount);
+        if (userAfter.amount - userBefore.amount !== BigInt(price.toNumber())) throw new Error("User should get only the credits back");
+        if (await provider.connection.getAccountInfo(vaultPDA) !== null) throw new Error("Vault was not closed");
+        await program.methods
+            .sweepTreasury(null)
+            .accounts({mainState: mainStatePDA, payout: payer.publicKey, treasuryVault: treasuryVaultPDA, payoutTokenAccount: ownerTokenAccount, tokenProgram: TOKEN_PROGRAM_ID})
+            .rpc();
+
+        await transfer(provider.connection, user, userTokenAccount, treasuryVaultPDA, user, dust);
+        const ownerBefore = await getAccount(provider.connection, ownerTokenAccount);
+        await program.methods
+            .closeMainState()
+            .accounts({mainState: mainStatePDA, treasuryVault: treasuryVaultPDA, signerTokenAccount: ownerTokenAccount, tokenProgram: TOKEN_PROGRAM_ID})
+            .rpc();
+        const ownerAfter = await getAccount(provider.connection, ownerTokenAccount);
+        if (ownerAfter.amount - ownerBefore.amount !== BigInt(dust)) throw new Error("Owner should get the tokens sent to the treasury vault");
+        if (await provider.connection.getAccountInfo(treasuryVaultPDA) !== null) throw new Error("Treasury vault was not closed");
+    });
+});
@@ -0,0 +1,74 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
+import { LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
+
+
+describe("trial", () => {
+    const provider = anchor.AnchorProvider.env()
+
+    anchor.setProvider(provider);
+
+    const idl = require("../target/idl/w_3_subs_tracker.json") as any;
+    const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    const tenantId = new BN(Date.now());
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const planId = new BN(Date.now());
+    const [planPDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), planId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const trialPeriod = new BN(1000 * 60 * 60 * 24 * 7);
+    const user = anchor.web3.Keypair.generate();
+    const [subsPDA, ___] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId);
+
+    it("Should init main state and the plan with a 7 day trial", async () => {
+        try {
+            await program.account.mainState.fetch(mainStatePDA);
+        } catch {
+            await program.methods
+                .intializeMainState(tenantId, feeSchedule, configDelay)
+                .rpc({skipPreflight: true});
+        }
+        const tx = await provider.connection.requestAirdrop(user.publicKey, LAMPORTS_PER_SOL);
+        await provider.connection.confirmTransaction(tx);
+        await program.methods
+            .createPlan(planId, "basic", new BN(LAMPORTS_PER_SOL * 0.3), new BN(1000 * 60 * 60 * 24 * 30), 1)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        await program.methods
+            .setPlanTrial(trialPeriod)
+            .accounts({mainState: mainStatePDA, plan: planPDA})
+            .rpc();
+    });
+
+    it("Should start the trial without any deposit and grant the access", async () => {
+        await program.methods
+            .startTrial()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, mint: null, vault: null, tokenProgram: null})
+            .signers([user])
+            .rpc();
+        const subsInfo = await program.account.subscription.fetch(subsPDA);
+        if (!subsInfo.trialUsed || !subsInfo.ledger.credits.isZero()) throw new Error("Trial subscription is not set correctly");
+        if (subsInfo.authorityWritable.currentPlan.toBase58() !== planPDA.toBase58()) throw new Error("Trial plan is not the current plan");
+        const validTill = subsInfo.authorityWritable.validTill.toNumber();
+        if (validTill < Date.now() + trialPeriod.toNumber() - 60_000) throw new Error("Trial period is too short");
+        const access = await program.methods
+            .verifyAccess(1)
+            .accounts({mainState: mainStatePDA, plan: planPDA, user: user.publicKey})
+            .view();
+        if (!access.eq(subsInfo.authorityWritable.validTill)) throw new Error("Trial doesn't grant the access");
+    });
+
+    it("Shouldn't let the wallet start the trial again", async () => {
+        let err = null;
+        try {
+            await program.methods
+                .startTrial()
+                .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA, mint: null, vault: null, tokenProgram: null})
+                .signers([user])
+                .rpc();
+            err = "Trial was started twice by the same wallet";
+        } catch (e) {}
+        if (err) throw new Error(err);
+    });
+});
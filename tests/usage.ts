@@ -0,0 +1,103 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { W3SubsTracker } from "../target/types/w_3_subs_tracker";
+import { LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { BN } from "bn.js";
+import { configDelay, feeSchedule } from "./config";
+import { expectError } from "./helpers";
+
+
+describe("usage", () => {
+    const provider = anchor.AnchorProvider.env()
+
+    anchor.setProvider(provider);
+
+    const idl = require("../target/idl/w_3_subs_tracker.json") as any;
+    const program = new anchor.Program(idl, provider) as Program<W3SubsTracker>;
+    const tenantId = new BN(Date.now());
+    const [mainStatePDA, _] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('mainState'), provider.wallet.publicKey.toBuffer(), tenantId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    const planId = new BN(1);
+    const [planPDA, __] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('plan'), mainStatePDA.toBuffer(), planId.toArrayLike(Buffer, 'le', 8)], program.programId);
+    // metered plan without the base price
+    const unitPrice = new BN(LAMPORTS_PER_SOL * 0.001);
+    const usageCap = new BN(100);
+    const user = anchor.web3.Keypair.generate();
+    // verifier queued by the owner, it takes over only once the config delay passes
+    const queuedVerifier = anchor.web3.Keypair.generate();
+    const [subsPDA, ___] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from('subscription'), user.publicKey.toBuffer(), mainStatePDA.toBuffer()], program.programId);
+    // recorded by the provider wallet (the verifier granted at the initialization) unless other signer is given
+    const recordUsage = (units: number, authority: anchor.web3.Keypair | null = null) => {
+        const builder = program.methods
+            .recordUsage(new BN(units))
+            .accounts({mainState: mainStatePDA, plan: planPDA, user: user.publicKey, ...(authority ? {authority: authority.publicKey} : {})});
+        return authority ? builder.signers([authority]).rpc() : builder.rpc();
+    };
+
+    it("Should init main state with a queued verifier and an active subscription of the plan before the metering", async () => {
+        await program.methods
+            .intializeMainState(tenantId, feeSchedule, configDelay)
+            .rpc();
+        await program.methods
+            .grantRole({ verifier: {} }, queuedVerifier.publicKey)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        await program.methods
+            .createPlan(planId, "api", new BN(0), new BN(1000 * 60 * 60 * 24 * 30), 1)
+            .accounts({mainState: mainStatePDA})
+            .rpc();
+        const tx = await provider.connection.requestAirdrop(user.publicKey, LAMPORTS_PER_SOL);
+        await provider.connection.confirmTransaction(tx);
+        await program.methods
+            .createSubscription(new BN(LAMPORTS_PER_SOL * 0.05), null)
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc();
+        await program.methods
+            .activateSubscription()
+            .accounts({mainState: mainStatePDA, user: user.publicKey, plan: planPDA})
+            .signers([user])
+            .rpc();
+        await expectError(recordUsage(1), "NotMetered");
+    });
+
+    it("Should let only the owner set the metering", async () => {
+        await expectError(program.methods
+            .setPlanMetering(unitPrice, usageCap)
+            .accounts({mainState: mainStatePDA, plan: planPDA, signer: user.publicKey})
+            .signers([user])
+            .rpc(), "ConstraintAddress");
+        await program.methods
+            .setPlanMetering(unitPrice, usageCap)
+            .accounts({mainState: mainStatePDA, plan: planPDA})
+            .rpc();
+        const plan = await program.account.plan.fetch(planPDA);
+        if (!plan.unitPrice.eq(unitPrice) || !plan.usageCap.eq(usageCap)) throw new Error("Metering is not set");
+    });
+
+    it("Should charge the recorded units from the credits and count them for the period", async () => {
+        await expectError(recordUsage(1, user), "InvalidAuthority");
+        // the queued verifier isn't in force before the config delay passes
+        await expectError(recordUsage(1, queuedVerifier), "InvalidAuthority");
+        const before = await program.account.subscription.fetch(subsPDA);
+        await recordUsage(10);
+        await recordUsage(5);
+        const after = await program.account.subscription.fetch(subsPDA);
+        const charged = unitPrice.muln(15);
+        if (!after.usage.units.eqn(15) || !after.usage.charged.eq(charged)) throw new Error("Usage is not counted");
+        if (!before.ledger.credits.sub(after.ledger.credits).eq(charged)) throw new Error("Usage is not charged from the credits");
+        if (!after.ledger.earned.sub(before.ledger.earned).eq(charged)) throw new Error("Charged usage is not earned");
+    });
+
+    it("Should enforce the usage cap and the credits", async () => {
+        await expectError(recordUsage(86), "UsageCapExceeded");
+        // 35 units of credits are left
+        await program.methods
+            .setPlanMetering(unitPrice, null)
+            .accounts({mainState: mainStatePDA, plan: planPDA})
+            .rpc();
+        await expectError(recordUsage(36), "NotEnoughCredits");
+        await recordUsage(35);
+        const subscription = await program.account.subscription.fetch(subsPDA);
+        if (!subscription.ledger.credits.eqn(0) || !subscription.usage.units.eqn(50)) throw new Error("Credits are not used up");
+    });
+});